@@ -119,6 +119,7 @@ async fn handle_voice_event(event: IncomingEvent) {
     match event {
         IncomingEvent::Ready(r) => info!("[Voice Event] Ready: {r:?}"),
         IncomingEvent::UpdateState(s) => info!("[Voice Event] Update State; {s:?}"),
-        IncomingEvent::Event {event, ..} => info!("[Voice Event] Event: {event:?}")
+        IncomingEvent::Event {event, ..} => info!("[Voice Event] Event: {event:?}"),
+        _ => ()
     }
 }
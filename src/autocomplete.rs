@@ -0,0 +1,243 @@
+use std::sync::Arc;
+use std::time::Duration;
+use dashmap::DashMap;
+use crate::error::{HttpError, TrackIdError};
+use crate::model::search::youtube::YoutubeTrack;
+use crate::model::track::TrackId;
+use crate::rest::RestClient;
+use crate::source::SearchSource;
+
+#[cfg(feature = "serenity")]
+use serenity::all::{AutocompleteChoice as SerenityChoice, CommandInteraction, CreateAutocompleteResponse};
+#[cfg(feature = "twilight")]
+use twilight_model::application::command::{CommandOptionChoice, CommandOptionChoiceValue};
+#[cfg(feature = "twilight")]
+use twilight_model::application::interaction::{Interaction, InteractionData};
+#[cfg(feature = "twilight")]
+use twilight_model::application::interaction::application_command::{CommandDataOption, CommandOptionValue};
+#[cfg(feature = "twilight")]
+use twilight_model::http::interaction::{InteractionResponse, InteractionResponseData, InteractionResponseType};
+
+/// Maximum number of choices discord accepts in an autocomplete response.
+const MAX_CHOICES: usize = 25;
+/// Maximum length of the name and value of an autocomplete choice.
+const MAX_CHOICE_LENGTH: usize = 100;
+
+/// Tracks that can be suggested as autocomplete choices.
+pub trait Suggestion {
+    /// The title of the track.
+    fn title(&self) -> &str;
+    /// The duration of the track, `None` if unknown or if the track is a live stream.
+    fn duration(&self) -> Option<Duration>;
+    /// The identifier of the track, its encoded form is used as the value of the choice and later
+    /// decoded back using [`Autocomplete::selected`]. Tracks whose encoded identifier is longer
+    /// than 100 characters are not suggested.
+    fn id(&self) -> TrackId;
+}
+
+impl Suggestion for YoutubeTrack {
+    fn title(&self) -> &str {
+        &self.title
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        (!self.is_stream).then(|| Duration::from_millis(self.length as _))
+    }
+
+    fn id(&self) -> TrackId {
+        YoutubeTrack::id(self)
+    }
+}
+
+/// A single autocomplete choice.
+#[derive(Debug, Clone)]
+pub struct AutocompleteChoice {
+    /// The name shown to the user, containing the truncated title and the duration of the track.
+    pub name: String,
+    /// The encoded identifier of the track, see [`TrackId::encode`].
+    pub value: String
+}
+
+impl AutocompleteChoice {
+    /// Creates the choice of the provided track, returns `None` if its encoded identifier is too
+    /// long to be used as a value, like the ones of long links.
+    fn new(track: &impl Suggestion) -> Option<Self> {
+        let value = track.id().encode();
+        if value.chars().count() > MAX_CHOICE_LENGTH {
+            return None;
+        }
+
+        let duration = match track.duration() {
+            Some(duration) => format_duration(duration),
+            None => String::from("LIVE")
+        };
+
+        let suffix = format!(" ({duration})");
+        let title = truncate(track.title(), MAX_CHOICE_LENGTH - suffix.chars().count());
+
+        Some(Self {
+            name: format!("{title}{suffix}"),
+            value
+        })
+    }
+}
+
+/// Helper that answers autocomplete interactions by making debounced searches on the provided
+/// source.
+///
+/// Searches are debounced per user, if a newer query arrives from the same user before the debounce
+/// time elapses, the older one is discarded without reaching the server.
+#[derive(Clone)]
+pub struct Autocomplete<S> {
+    http: RestClient,
+    source: S,
+    debounce: Duration,
+    pending: Arc<DashMap<u64, u64>>
+}
+
+impl<S> Autocomplete<S>
+where
+    S: SearchSource + Clone,
+    S::Track: Suggestion
+{
+    pub(crate) fn new(http: RestClient, source: S) -> Self {
+        Self {
+            http,
+            source,
+            debounce: Duration::from_millis(300),
+            pending: Arc::new(DashMap::new())
+        }
+    }
+
+    /// Sets the debounce time, the default value is 300 milliseconds.
+    pub fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Searches for `query` on behalf of `user`, returning the choices to show.
+    ///
+    /// Returns `None` if a newer query from the same user superseded this one.
+    pub async fn suggest(&self, user: u64, query: &str)
+        -> Result<Option<Vec<AutocompleteChoice>>, HttpError>
+    {
+        let query = query.trim();
+        if query.is_empty() {
+            return Ok(Some(Vec::new()));
+        }
+
+        let generation = {
+            let mut entry = self.pending.entry(user).or_insert(0);
+            *entry = entry.wrapping_add(1);
+            *entry
+        };
+
+        tokio::time::sleep(self.debounce).await;
+
+        if self.pending.get(&user).map(|g| *g) != Some(generation) {
+            return Ok(None);
+        }
+
+        self.pending.remove_if(&user, |_, g| *g == generation);
+
+        let results = self.http.search(query.to_string(), self.source.clone()).await?;
+
+        Ok(Some(results.iter()
+            .filter_map(AutocompleteChoice::new)
+            .take(MAX_CHOICES)
+            .collect()))
+    }
+
+    /// Decodes the value of the choice selected by the user back into a track identifier, which
+    /// can be played directly.
+    pub fn selected(value: &str) -> Result<TrackId, TrackIdError> {
+        TrackId::decode(value)
+    }
+
+    #[cfg(feature = "twilight")]
+    /// Answers the provided autocomplete interaction, returning a ready-made response containing
+    /// the choices for the focused option.
+    ///
+    /// Returns `None` if the interaction has no focused option or if a newer query from the same
+    /// user superseded this one.
+    pub async fn respond(&self, interaction: &Interaction)
+        -> Result<Option<InteractionResponse>, HttpError>
+    {
+        fn focused(options: &[CommandDataOption]) -> Option<&str> {
+            options.iter().find_map(|option| match &option.value {
+                CommandOptionValue::Focused(value, _) => Some(value.as_str()),
+                CommandOptionValue::SubCommand(options)
+                | CommandOptionValue::SubCommandGroup(options) => focused(options),
+                _ => None
+            })
+        }
+
+        let Some(InteractionData::ApplicationCommand(data)) = &interaction.data else {
+            return Ok(None);
+        };
+        let (Some(user), Some(query)) = (interaction.author_id(), focused(&data.options)) else {
+            return Ok(None);
+        };
+
+        let Some(choices) = self.suggest(user.get(), query).await? else {
+            return Ok(None);
+        };
+
+        Ok(Some(InteractionResponse {
+            kind: InteractionResponseType::ApplicationCommandAutocompleteResult,
+            data: Some(InteractionResponseData {
+                choices: Some(choices.into_iter()
+                    .map(|choice| CommandOptionChoice {
+                        name: choice.name,
+                        name_localizations: None,
+                        value: CommandOptionChoiceValue::String(choice.value)
+                    })
+                    .collect()),
+                ..Default::default()
+            })
+        }))
+    }
+
+    #[cfg(feature = "serenity")]
+    /// Answers the provided autocomplete interaction, returning a ready-made response containing
+    /// the choices for the focused option.
+    ///
+    /// Returns `None` if the interaction has no focused option or if a newer query from the same
+    /// user superseded this one.
+    pub async fn respond(&self, interaction: &CommandInteraction)
+        -> Result<Option<CreateAutocompleteResponse>, HttpError>
+    {
+        let Some(focused) = interaction.data.autocomplete() else {
+            return Ok(None);
+        };
+
+        let Some(choices) = self.suggest(interaction.user.id.get(), focused.value).await? else {
+            return Ok(None);
+        };
+
+        Ok(Some(CreateAutocompleteResponse::new().set_choices(choices.into_iter()
+            .map(|choice| SerenityChoice::new(choice.name, choice.value))
+            .collect())))
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, (secs / 60) % 60, secs % 60);
+
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes}:{seconds:02}")
+    }
+}
+
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+
+    let mut truncated = text.chars().take(max.saturating_sub(1)).collect::<String>();
+    truncated.push('…');
+    truncated
+}
@@ -0,0 +1,190 @@
+use std::borrow::Cow;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio_tungstenite::tungstenite::Message;
+use crate::config::Config;
+use crate::error::CodecError;
+
+#[cfg(feature = "zlib")]
+use flate2::{Decompress, FlushDecompress};
+
+/// Suffix of every complete message of a zlib stream, sent by the server after flushing the
+/// compressor.
+#[cfg(feature = "zlib")]
+const ZLIB_SUFFIX: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// The encoding used for gateway payloads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// Payloads are sent as JSON text frames.
+    #[default]
+    Json,
+    #[cfg(feature = "msgpack")]
+    /// Payloads are sent as MessagePack binary frames.
+    MessagePack
+}
+
+impl Encoding {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            #[cfg(feature = "msgpack")]
+            Self::MessagePack => "msgpack"
+        }
+    }
+}
+
+/// Encodes outgoing payloads and decodes incoming ones using the negotiated encoding and
+/// compression, a new codec must be used for every connection.
+pub(crate) struct Codec {
+    encoding: Encoding,
+    #[cfg(feature = "zlib")]
+    inflate: Option<Inflate>
+}
+
+impl Codec {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            encoding: config.encoding,
+            #[cfg(feature = "zlib")]
+            inflate: config.compress.then(Inflate::new)
+        }
+    }
+
+    /// Returns the query parameters used to negotiate the encoding and compression with the
+    /// server.
+    pub fn query(&self) -> String {
+        #[allow(unused_mut)]
+        let mut query = format!("encoding={}", self.encoding.as_str());
+
+        #[cfg(feature = "zlib")]
+        if self.inflate.is_some() {
+            query.push_str("&compress=zlib-stream");
+        }
+
+        query
+    }
+
+    /// Encodes an outgoing payload into a websocket message.
+    pub fn encode(&self, payload: &Value) -> Result<Message, CodecError> {
+        match self.encoding {
+            Encoding::Json => Ok(Message::Text(serde_json::to_string(payload)?)),
+            #[cfg(feature = "msgpack")]
+            Encoding::MessagePack => Ok(Message::Binary(rmp_serde::to_vec_named(payload)?))
+        }
+    }
+
+    /// Decodes a payload received in a text frame, which are always JSON encoded.
+    pub fn decode_text<T: DeserializeOwned>(&self, data: &str) -> Result<T, CodecError> {
+        Ok(serde_json::from_str(data)?)
+    }
+
+    /// Decompresses the content of a binary frame if compression is enabled, returns `None` if
+    /// the frame only contains part of a compressed message.
+    pub fn inflate<'a>(&mut self, data: &'a [u8]) -> Result<Option<Cow<'a, [u8]>>, CodecError> {
+        #[cfg(feature = "zlib")]
+        if let Some(inflate) = self.inflate.as_mut() {
+            return Ok(inflate.push(data)?.map(Cow::Owned));
+        }
+
+        Ok(Some(Cow::Borrowed(data)))
+    }
+
+    /// Decodes the content of a binary frame, after being decompressed using [`Codec::inflate`].
+    pub fn decode_binary<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T, CodecError> {
+        match self.encoding {
+            Encoding::Json => Ok(serde_json::from_slice(data)?),
+            #[cfg(feature = "msgpack")]
+            Encoding::MessagePack => Ok(rmp_serde::from_slice(data)?)
+        }
+    }
+}
+
+#[cfg(feature = "zlib")]
+/// Shared zlib context of a connection, messages are split across frames and can only be
+/// inflated once the whole message has been received.
+struct Inflate {
+    decompress: Decompress,
+    buffer: Vec<u8>
+}
+
+#[cfg(feature = "zlib")]
+impl Inflate {
+    fn new() -> Self {
+        Self {
+            decompress: Decompress::new(true),
+            buffer: Vec::new()
+        }
+    }
+
+    fn push(&mut self, data: &[u8]) -> Result<Option<Vec<u8>>, CodecError> {
+        self.buffer.extend_from_slice(data);
+
+        if !self.buffer.ends_with(&ZLIB_SUFFIX) {
+            return Ok(None);
+        }
+
+        let input = std::mem::take(&mut self.buffer);
+        let mut output = Vec::with_capacity(input.len() * 4);
+        let start = self.decompress.total_in();
+
+        loop {
+            if output.len() == output.capacity() {
+                output.reserve(input.len());
+            }
+
+            let consumed = (self.decompress.total_in() - start) as usize;
+            let (total_in, total_out) = (self.decompress.total_in(), self.decompress.total_out());
+            self.decompress.decompress_vec(&input[consumed..], &mut output, FlushDecompress::Sync)?;
+
+            // A full output buffer may leave data pending inside the decompressor even after all
+            // the input was consumed, so keep going until it stops filling the buffer.
+            let consumed = (self.decompress.total_in() - start) as usize;
+            if consumed >= input.len() && output.len() < output.capacity() {
+                break;
+            }
+
+            if self.decompress.total_in() == total_in && self.decompress.total_out() == total_out {
+                break;
+            }
+        }
+
+        Ok(Some(output))
+    }
+}
+
+#[cfg(all(test, feature = "zlib"))]
+mod tests {
+    use flate2::{Compress, Compression, FlushCompress};
+    use super::*;
+
+    fn compress(compress: &mut Compress, data: &[u8]) -> Vec<u8> {
+        let mut output = Vec::with_capacity(data.len() + 64);
+        compress.compress_vec(data, &mut output, FlushCompress::Sync).unwrap();
+        assert!(output.ends_with(&ZLIB_SUFFIX));
+        output
+    }
+
+    #[test]
+    fn inflate_split_frames() {
+        for repeat in (10..2000).step_by(10) {
+            let mut compressor = Compress::new(Compression::best(), true);
+            let mut inflate = Inflate::new();
+
+            for seq in 0..3 {
+                // Highly compressible, so the output is far larger than 4 times the input.
+                let payload = format!("{{\"op\":\"event\",\"seq\":{seq}}}")
+                    .repeat(repeat)
+                    .into_bytes();
+                let compressed = compress(&mut compressor, &payload);
+                let (first, rest) = compressed.split_at(compressed.len() / 3);
+                let (second, third) = rest.split_at(rest.len() / 2);
+
+                assert!(inflate.push(first).unwrap().is_none());
+                assert!(inflate.push(second).unwrap().is_none());
+                let inflated = inflate.push(third).unwrap().unwrap();
+                assert_eq!(inflated, payload, "repeat {repeat}, seq {seq}");
+            }
+        }
+    }
+}
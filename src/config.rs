@@ -1,5 +1,12 @@
 use std::num::NonZeroU64;
+use std::sync::Arc;
 use std::time::Duration;
+use crate::codec::Encoding;
+use crate::endpoint::Endpoint;
+use crate::error::EndpointError;
+use crate::middleware::Middleware;
+
+pub use native_tls::TlsConnector;
 
 #[derive(Clone)]
 pub struct Config {
@@ -9,7 +16,54 @@ pub struct Config {
     pub ssl: bool,
     pub user_id: NonZeroU64,
     pub shards: u64,
-    pub connection_attempts: u32
+    pub connection_attempts: u32,
+    /// Retry policy applied to every REST request.
+    pub retry: RetryPolicy,
+    /// Timeout for establishing connections to the server, `None` waits indefinitely.
+    pub connect_timeout: Option<Duration>,
+    /// Timeout for REST requests, `None` waits indefinitely.
+    pub request_timeout: Option<Duration>,
+    /// Timeout for search and playlist requests, `None` uses the request timeout.
+    pub search_timeout: Option<Duration>,
+    /// Middlewares executed around every REST request.
+    pub middleware: Vec<Arc<dyn Middleware>>,
+    /// Endpoint of the server, overrides the `host`, `port` and `ssl` fields when present.
+    pub endpoint: Option<Endpoint>,
+    /// HTTP client used to make REST requests. When present, the `connect_timeout`, `tls` and
+    /// `proxy` fields don't apply to REST requests, and must be configured on the client itself.
+    pub http_client: Option<reqwest::Client>,
+    /// TLS connector used for both REST requests and the websocket connection, allowing to use
+    /// custom root certificates or client certificates.
+    pub tls: Option<TlsConnector>,
+    /// Proxy used for REST requests.
+    pub proxy: Option<reqwest::Proxy>,
+    /// Interval between websocket pings sent to the server, `None` disables heartbeats.
+    pub heartbeat_interval: Option<Duration>,
+    /// Number of consecutive pings without response after which the connection is considered
+    /// dead and a reconnection is attempted.
+    pub max_missed_heartbeats: u32,
+    /// Maximum number of outgoing messages buffered while disconnected, they are sent once the
+    /// connection is established again. Only the latest voice update of every guild is kept.
+    pub outgoing_buffer_size: usize,
+    /// Whether every payload sent to and received from the server is logged at `TRACE` level
+    /// under the `nightingale_client::tap` target, passwords and voice tokens are redacted.
+    pub trace_payloads: bool,
+    #[cfg(feature = "serenity")]
+    /// How events are delivered to the [`EventHandler`].
+    ///
+    /// [`EventHandler`]: crate::events::EventHandler
+    pub dispatch_mode: DispatchMode,
+    /// Maximum time the [`EventHandler`] can spend on a player or voice event before being
+    /// cancelled, `None` disables the timeout.
+    ///
+    /// [`EventHandler`]: crate::events::EventHandler
+    pub handler_timeout: Option<Duration>,
+    /// Encoding used for gateway payloads, negotiated with the server when connecting.
+    pub encoding: Encoding,
+    #[cfg(feature = "zlib")]
+    /// Whether the server should compress gateway payloads using a shared zlib stream, this is
+    /// used instead of permessage-deflate, which the websocket client doesn't support.
+    pub compress: bool
 }
 
 impl Default for Config {
@@ -21,13 +75,97 @@ impl Default for Config {
             ssl: false,
             user_id: unsafe { NonZeroU64::new_unchecked(1) },
             shards: 1,
-            connection_attempts: 5
+            connection_attempts: 5,
+            retry: RetryPolicy::default(),
+            connect_timeout: Some(Duration::from_secs(10)),
+            request_timeout: Some(Duration::from_secs(15)),
+            search_timeout: Some(Duration::from_secs(30)),
+            middleware: Vec::new(),
+            endpoint: None,
+            http_client: None,
+            tls: None,
+            proxy: None,
+            heartbeat_interval: Some(Duration::from_secs(30)),
+            max_missed_heartbeats: 2,
+            outgoing_buffer_size: 100,
+            trace_payloads: false,
+            #[cfg(feature = "serenity")]
+            dispatch_mode: DispatchMode::default(),
+            handler_timeout: None,
+            encoding: Encoding::default(),
+            #[cfg(feature = "zlib")]
+            compress: false
+        }
+    }
+}
+
+impl Config {
+    /// Parses and sets the endpoint of the server, see [`Endpoint::parse`] for the accepted
+    /// formats.
+    pub fn with_endpoint(mut self, endpoint: &str) -> Result<Self, EndpointError> {
+        self.endpoint = Some(Endpoint::parse(endpoint)?);
+        Ok(self)
+    }
+}
+
+#[cfg(feature = "serenity")]
+/// How events are delivered to the [`EventHandler`].
+///
+/// [`EventHandler`]: crate::events::EventHandler
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DispatchMode {
+    /// Every event is handled in its own task, events can be handled out of order.
+    #[default]
+    Concurrent,
+    /// Events of the same guild are handled one after another in the order they were received,
+    /// while events of different guilds are still handled concurrently.
+    OrderedPerGuild
+}
+
+/// Policy used to retry failed REST requests.
+///
+/// Requests are retried when the connection fails or the server responds with `429 Too Many
+/// Requests` or `503 Service Unavailable`, in which case the `Retry-After` header is respected.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Maximum number of retries made for a single request, `0` disables retries.
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled on each subsequent one.
+    pub base_delay: Duration,
+    /// Maximum delay between retries, this also caps the delay requested by the server.
+    pub max_delay: Duration,
+    /// Whether to also retry non idempotent requests, by default only `GET`, `PUT` and `PATCH`
+    /// requests are retried.
+    pub non_idempotent: bool
+}
+
+impl RetryPolicy {
+    /// Returns a policy that never retries requests.
+    pub fn disabled() -> Self {
+        Self {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn backoff(&self, retries: u32) -> Duration {
+        self.base_delay.saturating_mul(2u32.saturating_pow(retries))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            non_idempotent: false
         }
     }
 }
 
 pub struct SessionConfig {
-    enable_resume: bool,
+    pub(crate) enable_resume: bool,
     reconnect_time: Duration
 }
 
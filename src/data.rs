@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use parking_lot::RwLock;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use typemap_rev::{TypeMap, TypeMapKey};
+
+/// Keys whose values are saved in player snapshots, see [`Player::snapshot`].
+///
+/// [`Player::snapshot`]: crate::player::Player::snapshot
+pub trait PersistentKey: TypeMapKey
+where
+    Self::Value: Serialize + DeserializeOwned
+{
+    /// Unique name the value is stored under in snapshots.
+    const NAME: &'static str;
+}
+
+type SerializeFn = fn(&TypeMap) -> Option<Value>;
+
+/// Concurrent store of custom data attached to a player, like an announcement channel or a DJ
+/// role. The store can be used through shared references.
+///
+/// Locks are only held while the methods run, so they can't be held across awaits.
+#[derive(Default)]
+pub struct PlayerData {
+    map: RwLock<TypeMap>,
+    persistent: RwLock<HashMap<&'static str, SerializeFn>>
+}
+
+impl PlayerData {
+    /// Returns a copy of the value of the provided key, if present.
+    pub fn get<K>(&self) -> Option<K::Value>
+    where
+        K: TypeMapKey,
+        K::Value: Clone
+    {
+        self.map.read().get::<K>().cloned()
+    }
+
+    /// Returns whether a value is present for the provided key.
+    pub fn contains<K: TypeMapKey>(&self) -> bool {
+        self.map.read().contains_key::<K>()
+    }
+
+    /// Inserts a value, replacing the previous one.
+    pub fn insert<K: TypeMapKey>(&self, value: K::Value) {
+        self.map.write().insert::<K>(value);
+    }
+
+    /// Inserts a value that is saved in the snapshots of the player, replacing the previous one.
+    pub fn insert_persistent<K>(&self, value: K::Value)
+    where
+        K: PersistentKey,
+        K::Value: Serialize + DeserializeOwned
+    {
+        self.map.write().insert::<K>(value);
+        self.persistent.write().insert(K::NAME, serialize::<K>);
+    }
+
+    /// Removes the value of the provided key, returning it if present.
+    pub fn remove<K: TypeMapKey>(&self) -> Option<K::Value> {
+        self.map.write().remove::<K>()
+    }
+
+    /// Updates the value of the provided key, returns `None` if there is no value.
+    pub fn update<K, R>(&self, f: impl FnOnce(&mut K::Value) -> R) -> Option<R>
+    where
+        K: TypeMapKey
+    {
+        self.map.write().get_mut::<K>().map(f)
+    }
+
+    /// Restores the value of a persistent key from the data of a snapshot, returns whether a
+    /// value was present and could be deserialized.
+    pub fn restore<K>(&self, data: &HashMap<String, Value>) -> bool
+    where
+        K: PersistentKey,
+        K::Value: Serialize + DeserializeOwned
+    {
+        let Some(value) = data.get(K::NAME) else { return false; };
+
+        match serde_json::from_value(value.clone()) {
+            Ok(value) => {
+                self.insert_persistent::<K>(value);
+                true
+            },
+            Err(_) => false
+        }
+    }
+
+    /// Serializes the values of the persistent keys.
+    pub(crate) fn persistent_values(&self) -> HashMap<String, Value> {
+        let map = self.map.read();
+
+        self.persistent.read().iter()
+            .filter_map(|(name, serialize)| Some((name.to_string(), serialize(&map)?)))
+            .collect()
+    }
+}
+
+fn serialize<K>(map: &TypeMap) -> Option<Value>
+where
+    K: PersistentKey,
+    K::Value: Serialize + DeserializeOwned
+{
+    serde_json::to_value(map.get::<K>()?).ok()
+}
@@ -0,0 +1,196 @@
+#[cfg(feature = "serenity")]
+use std::collections::HashMap;
+#[cfg(feature = "serenity")]
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "serenity")]
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+#[cfg(feature = "serenity")]
+use tokio::task::JoinHandle;
+use tracing::warn;
+use crate::events::EventHandler;
+use crate::manager::PlayerManager;
+use crate::model::gateway::event::Event;
+use crate::model::gateway::state::UpdateState;
+#[cfg(feature = "serenity")]
+use crate::Shared;
+#[cfg(feature = "serenity")]
+use crate::model::gateway::close::DisconnectReason;
+#[cfg(feature = "serenity")]
+use crate::model::gateway::connection::ConnectionState;
+#[cfg(feature = "serenity")]
+use crate::model::gateway::ready::Ready;
+
+/// Events that belong to a single guild.
+pub(crate) enum GuildEvent {
+    UpdateState(UpdateState),
+    Event {
+        event: Event,
+        replayed: bool
+    }
+}
+
+#[cfg(feature = "serenity")]
+/// Events about the connection with the server.
+pub(crate) enum ConnectionEvent {
+    Ready(Ready),
+    State(ConnectionState),
+    Disconnected(Option<DisconnectReason>),
+    EventsMissed {
+        first: u64,
+        last: u64
+    }
+}
+
+#[cfg(feature = "serenity")]
+/// Starts a worker that delivers the connection events one after another, in the order they
+/// were sent, until the returned sender is dropped.
+pub(crate) fn connection_worker(events: Arc<dyn EventHandler>) -> UnboundedSender<ConnectionEvent> {
+    let (tx, mut rx) = unbounded_channel();
+
+    tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            match event {
+                ConnectionEvent::Ready(ready) => events.on_ready(ready).await,
+                ConnectionEvent::State(state) => events.on_connection_state(state).await,
+                ConnectionEvent::Disconnected(reason) => events.on_disconnect(reason).await,
+                ConnectionEvent::EventsMissed { first, last } => {
+                    events.on_events_missed(first, last).await
+                }
+            }
+        }
+    });
+
+    tx
+}
+
+#[cfg(feature = "serenity")]
+/// Delivers the events of every guild in order, using a worker task per guild so different
+/// guilds are still handled concurrently.
+pub(crate) struct GuildDispatcher {
+    workers: HashMap<u64, (UnboundedSender<GuildEvent>, JoinHandle<()>)>,
+    /// Workers that were stopped but may still be handling queued events, the next worker of
+    /// the same guild waits for them to finish.
+    stopping: HashMap<u64, JoinHandle<()>>,
+    events: Arc<dyn EventHandler>,
+    players: Arc<PlayerManager>,
+    shared: Arc<Shared>
+}
+
+#[cfg(feature = "serenity")]
+impl GuildDispatcher {
+    pub fn new(
+        events: Arc<dyn EventHandler>,
+        players: Arc<PlayerManager>,
+        shared: Arc<Shared>
+    ) -> Self {
+        Self {
+            workers: HashMap::new(),
+            stopping: HashMap::new(),
+            events,
+            players,
+            shared
+        }
+    }
+
+    /// Queues an event, it will be delivered after the previous events of the same guild were
+    /// handled.
+    ///
+    /// The worker of the guild stops after delivering a gateway disconnection, a new one is
+    /// started when the next event of the guild arrives, which waits for the previous one to
+    /// finish.
+    pub fn dispatch(&mut self, guild: u64, event: GuildEvent) {
+        let disconnected = matches!(
+            event,
+            GuildEvent::UpdateState(UpdateState::DisconnectGateway(_))
+        );
+        let (sender, _) = self.workers.entry(guild).or_insert_with(|| {
+            let (tx, rx) = unbounded_channel();
+            let task = tokio::spawn(worker(
+                guild,
+                rx,
+                self.stopping.remove(&guild),
+                Arc::clone(&self.events),
+                Arc::clone(&self.players),
+                Arc::clone(&self.shared)
+            ));
+
+            (tx, task)
+        });
+
+        let _ = sender.send(event);
+
+        if disconnected {
+            // Dropping the sender stops the worker once it handled the queued events.
+            if let Some((_, task)) = self.workers.remove(&guild) {
+                self.stopping.retain(|_, task| !task.is_finished());
+                self.stopping.insert(guild, task);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serenity")]
+/// Delivers the events of a guild one after another, until its sender is removed from the
+/// dispatcher. The events are only handled after the previous worker of the guild finished.
+async fn worker(
+    guild: u64,
+    mut receiver: UnboundedReceiver<GuildEvent>,
+    previous: Option<JoinHandle<()>>,
+    events: Arc<dyn EventHandler>,
+    players: Arc<PlayerManager>,
+    shared: Arc<Shared>
+) {
+    if let Some(previous) = previous {
+        let _ = previous.await;
+    }
+
+    while let Some(event) = receiver.recv().await {
+        let timeout = shared.config.read().handler_timeout;
+        deliver(&*events, &players, guild, event, timeout).await;
+    }
+}
+
+/// Delivers an event to the handler, giving up if it takes longer than `timeout`.
+///
+/// Handlers receive a snapshot of the player, so they don't hold its lock while running.
+pub(crate) async fn deliver(
+    events: &dyn EventHandler,
+    players: &PlayerManager,
+    guild: u64,
+    event: GuildEvent,
+    timeout: Option<Duration>
+) {
+    let handler = async {
+        match event {
+            GuildEvent::UpdateState(state) => match state {
+                UpdateState::ConnectGateway(data) => events.on_gateway_connect(data).await,
+                UpdateState::ReconnectGateway(data) => events.on_gateway_reconnect(data).await,
+                UpdateState::DisconnectGateway(data) => events.on_gateway_disconnect(data).await
+            },
+            GuildEvent::Event { event, replayed } => {
+                let player = players.get_or_insert(guild).clone();
+
+                if replayed {
+                    events.on_replayed_event(&player, event).await;
+                    return;
+                }
+
+                match event {
+                    Event::TrackStart(t) => events.on_track_start(&player, t).await,
+                    Event::TrackEnd(t) => events.on_track_end(&player, t).await,
+                    Event::TrackErrored(t) => events.on_track_errored(&player, t).await
+                }
+            }
+        }
+    };
+
+    let Some(timeout) = timeout else {
+        handler.await;
+        return;
+    };
+
+    if tokio::time::timeout(timeout, handler).await.is_err() {
+        warn!("Event handler for guild {} didn't finish after {:?}, cancelling it", guild, timeout);
+    }
+}
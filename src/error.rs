@@ -1,12 +1,33 @@
+use std::sync::Arc;
 use reqwest::StatusCode;
 use thiserror::Error;
-use crate::model::error::ErrorResponse;
+use crate::model::gateway::close::DisconnectReason;
+use crate::model::error::{ErrorCode, ErrorResponse, is_retryable_status};
 
 #[derive(Debug, Error)]
-#[error(transparent)]
 pub(crate) enum SocketError {
+    #[error(transparent)]
     Tungstenite(#[from] tokio_tungstenite::tungstenite::Error),
-    Deserialize(#[from] serde_json::Error)
+    #[error(transparent)]
+    Decode(#[from] CodecError),
+    #[error("Connection closed by the server")]
+    Closed(Option<DisconnectReason>)
+}
+
+/// Errors that can happen when encoding or decoding gateway payloads.
+#[derive(Debug, Error)]
+pub(crate) enum CodecError {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[cfg(feature = "msgpack")]
+    #[error(transparent)]
+    MessagePackDecode(#[from] rmp_serde::decode::Error),
+    #[cfg(feature = "msgpack")]
+    #[error(transparent)]
+    MessagePackEncode(#[from] rmp_serde::encode::Error),
+    #[cfg(feature = "zlib")]
+    #[error(transparent)]
+    Decompress(#[from] flate2::DecompressError)
 }
 
 /// Error status code returned from the server.
@@ -20,7 +41,148 @@ pub enum HttpError {
     #[error("Server returned an error message: {0:?}")]
     ErrorMessage(#[from] ErrorResponse),
     #[error(transparent)]
-    Reqwest(#[from] reqwest::Error),
+    Reqwest(reqwest::Error),
+    /// The request timed out, the source is only present for requests made using reqwest, and
+    /// not for requests made over a unix domain socket.
+    #[error("Request timed out")]
+    Timeout(#[source] Option<reqwest::Error>),
+    /// The endpoint of the server set in the [`Config`] is invalid.
+    ///
+    /// [`Config`]: crate::config::Config
+    #[error("Invalid endpoint: {0}")]
+    InvalidEndpoint(#[from] EndpointError),
+    /// The HTTP client couldn't be built using the TLS connector or proxy set in the
+    /// [`Config`].
+    ///
+    /// [`Config`]: crate::config::Config
+    #[error("Failed to build the HTTP client: {0}")]
+    Client(#[source] Arc<reqwest::Error>),
+    /// The [`PlayOptions`] used to play a track contradict each other.
+    ///
+    /// [`PlayOptions`]: crate::player::PlayOptions
+    #[error("Invalid play options: {0}")]
+    InvalidOptions(&'static str),
+    #[error("Transport error: {0}")]
+    Transport(Box<dyn std::error::Error + Send + Sync>),
+    #[error("Failed to deserialize response: {0}")]
+    Deserialize(#[from] serde_json::Error),
     #[error("Server responded with an unexpected status code: {0:?}")]
-    UnexpectedStatus(#[from] StatusCodeError)
+    UnexpectedStatus(#[from] StatusCodeError),
+    #[error("Request failed after {retries} retries: {error}")]
+    Retried {
+        /// Number of retries made before giving up.
+        retries: u32,
+        /// The error returned by the last attempt.
+        #[source]
+        error: Box<HttpError>
+    }
+}
+
+impl From<reqwest::Error> for HttpError {
+    fn from(value: reqwest::Error) -> Self {
+        if value.is_timeout() {
+            Self::Timeout(Some(value))
+        } else {
+            Self::Reqwest(value)
+        }
+    }
+}
+
+impl HttpError {
+    /// Returns the error returned by the server, if any.
+    pub fn error_response(&self) -> Option<&ErrorResponse> {
+        match self {
+            Self::ErrorMessage(e) => Some(e),
+            Self::Retried { error, .. } => error.error_response(),
+            _ => None
+        }
+    }
+
+    /// Returns the code of the error returned by the server, if any.
+    pub fn code(&self) -> Option<ErrorCode> {
+        self.error_response().map(|e| e.code)
+    }
+
+    /// Returns the status code the server responded with, if any.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::ErrorMessage(e) => e.status,
+            Self::Reqwest(e) => e.status(),
+            Self::Timeout(e) => e.as_ref().and_then(reqwest::Error::status),
+            Self::InvalidEndpoint(_) | Self::Client(_) | Self::Transport(_) => None,
+            Self::InvalidOptions(_) => None,
+            Self::UnexpectedStatus(StatusCodeError(status)) => Some(*status),
+            Self::Deserialize(_) => None,
+            Self::Retried { error, .. } => error.status()
+        }
+    }
+
+    /// Returns whether the requested resource, like a player or session, does not exist.
+    pub fn is_not_found(&self) -> bool {
+        match self.error_response() {
+            Some(e) => e.is_not_found(),
+            None => self.status() == Some(StatusCode::NOT_FOUND)
+        }
+    }
+
+    /// Returns whether making the same request again could succeed, this includes timeouts,
+    /// connection errors and rate limits.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::ErrorMessage(e) => e.is_retryable(),
+            Self::Timeout(_) | Self::Transport(_) => true,
+            Self::Reqwest(e) => e.is_connect() || e.status().is_some_and(is_retryable_status),
+            Self::UnexpectedStatus(StatusCodeError(status)) => is_retryable_status(*status),
+            Self::InvalidEndpoint(_) | Self::Client(_) | Self::Deserialize(_) => false,
+            Self::InvalidOptions(_) => false,
+            Self::Retried { error, .. } => error.is_retryable()
+        }
+    }
+
+    /// Returns whether the request timed out, meaning the server is slow or unreachable.
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            Self::Timeout(_) => true,
+            Self::Retried { error, .. } => error.is_timeout(),
+            _ => false
+        }
+    }
+
+    /// Returns the number of retries made before this error was returned.
+    pub fn retries(&self) -> u32 {
+        match self {
+            Self::Retried { retries, .. } => *retries,
+            _ => 0
+        }
+    }
+}
+
+/// Errors that can be returned when decoding a [`TrackId`].
+///
+/// [`TrackId`]: crate::model::track::TrackId
+#[derive(Debug, Error)]
+pub enum TrackIdError {
+    #[error("Unknown track id kind: {0}")]
+    UnknownKind(String),
+    #[error("Malformed track id")]
+    Malformed,
+    #[error("Invalid track id encoding")]
+    InvalidEncoding
+}
+
+/// Errors that can be returned when parsing an [`Endpoint`].
+///
+/// [`Endpoint`]: crate::endpoint::Endpoint
+#[derive(Debug, Clone, Error)]
+pub enum EndpointError {
+    #[error("Invalid url: {0}")]
+    InvalidUrl(#[from] url::ParseError),
+    #[error("Unsupported scheme: {0}")]
+    UnsupportedScheme(String),
+    #[error("The url has no host")]
+    MissingHost,
+    #[error("The url must not contain credentials, query or fragment")]
+    UnexpectedComponents,
+    #[error("Missing unix socket path")]
+    MissingSocketPath
 }
@@ -1,18 +1,28 @@
+use crate::model::gateway::close::DisconnectReason;
+use crate::model::gateway::connection::ConnectionState;
 use crate::model::gateway::ready::Ready;
-#[cfg(feature = "serenity")]
-use crate::model::gateway::event::{TrackEnd, TrackErrored};
-#[cfg(feature = "serenity")]
+use crate::model::gateway::event::{Event, TrackEnd, TrackErrored};
 use crate::model::gateway::state::{ConnectionData, DisconnectData};
-#[cfg(feature = "serenity")]
 use crate::model::track::Track;
-#[cfg(feature = "serenity")]
 use crate::player::Player;
 #[cfg(feature = "serenity")]
 use serenity::async_trait;
 #[cfg(feature = "twilight")]
+use async_trait::async_trait;
+#[cfg(feature = "twilight")]
+use futures::StreamExt;
+#[cfg(feature = "twilight")]
 use tokio::sync::mpsc::UnboundedSender;
 #[cfg(feature = "twilight")]
-use crate::model::gateway::{event::Event, state::UpdateState, IncomingPayload};
+use crate::manager::PlayerManager;
+#[cfg(feature = "twilight")]
+use crate::model::gateway::{state::UpdateState, IncomingFrame, IncomingPayload};
+#[cfg(feature = "twilight")]
+use crate::stream::EventStream;
+#[cfg(feature = "twilight")]
+use crate::dispatch::{deliver, GuildEvent};
+#[cfg(feature = "twilight")]
+use crate::Shared;
 #[cfg(feature = "twilight")]
 use crate::msg::ToSocketMessage;
 #[cfg(feature = "twilight")]
@@ -20,12 +30,24 @@ use twilight_model::gateway::event::Event as TwilightEvent;
 #[cfg(feature = "twilight")]
 use serde_json::json;
 
-#[cfg(feature = "serenity")]
 /// Trait defining what events can be fired from the server.
+///
+/// With twilight, events are only delivered to a handler when using
+/// [`NightingaleClient::dispatch_events`].
+///
+/// [`NightingaleClient::dispatch_events`]: crate::NightingaleClient::dispatch_events
 #[async_trait]
 pub trait EventHandler: Send + Sync {
     /// Triggered after connecting to the server.
     async fn on_ready(&self, _ready: Ready) {}
+    /// Triggered when the connection with the server is lost, the reason is only present if the
+    /// server closed the connection itself.
+    async fn on_disconnect(&self, _reason: Option<DisconnectReason>) {}
+    /// Triggered when the state of the connection with the server changes.
+    async fn on_connection_state(&self, _state: ConnectionState) {}
+    /// Triggered when events with sequence numbers from `first` to `last` were lost and couldn't
+    /// be replayed by the server, the local state of the players may be out of date.
+    async fn on_events_missed(&self, _first: u64, _last: u64) {}
     /// Triggered when the server connects to a voice channel.
     async fn on_gateway_connect(&self, _info: ConnectionData) {}
     /// Triggered when the server reconnects to a voice channel due to some network issues.
@@ -39,13 +61,40 @@ pub trait EventHandler: Send + Sync {
     async fn on_track_end(&self, _player: &Player, _track_end: TrackEnd) {}
     /// Triggered when a track encountered an error when trying to play.
     async fn on_track_errored(&self, _player: &Player, _track_errored: TrackErrored) {}
+    /// Triggered when an event that happened while disconnected is replayed after resuming the
+    /// session, by default this calls the same method as a regular event.
+    async fn on_replayed_event(&self, player: &Player, event: Event) {
+        match event {
+            Event::TrackStart(t) => self.on_track_start(player, t).await,
+            Event::TrackEnd(t) => self.on_track_end(player, t).await,
+            Event::TrackErrored(t) => self.on_track_errored(player, t).await
+        }
+    }
 }
 
 #[cfg(feature = "twilight")]
 /// All possible incoming events from the server.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum IncomingEvent {
     /// Received after connecting to the server.
     Ready(Ready),
+    /// Received when the connection with the server is lost, the reason is only present if the
+    /// server closed the connection itself.
+    ///
+    /// The client only reconnects if the connection was closed abnormally, see
+    /// [`DisconnectReason::should_reconnect`].
+    Disconnected(Option<DisconnectReason>),
+    /// Received when the state of the connection with the server changes.
+    ConnectionState(ConnectionState),
+    /// Received when events with sequence numbers from `first` to `last` were lost and couldn't
+    /// be replayed by the server, the local state of the players may be out of date.
+    EventsMissed {
+        /// Sequence number of the first missed event.
+        first: u64,
+        /// Sequence number of the last missed event.
+        last: u64
+    },
     /// Received when a voice state change occurs.
     UpdateState(UpdateState),
     /// Received when a playback related event occurs.
@@ -53,18 +102,37 @@ pub enum IncomingEvent {
         /// The guild id the event belongs to.
         guild_id: u64,
         /// The event itself.
-        event: Event
+        event: Event,
+        /// Whether the event happened while disconnected and is being replayed after resuming the
+        /// session.
+        replayed: bool
+    }
+}
+
+#[cfg(feature = "twilight")]
+impl IncomingEvent {
+    /// Returns the id of the guild the event belongs to, if any.
+    pub fn guild_id(&self) -> Option<u64> {
+        match self {
+            Self::Event { guild_id, .. } => Some(*guild_id),
+            Self::UpdateState(state) => Some(state.guild_id().get()),
+            _ => None
+        }
     }
 }
 
 #[cfg(feature = "twilight")]
-impl From<IncomingPayload> for IncomingEvent {
-    fn from(value: IncomingPayload) -> Self {
-        match value {
+impl From<IncomingFrame> for IncomingEvent {
+    fn from(value: IncomingFrame) -> Self {
+        match value.payload {
             IncomingPayload::Ready(r) => Self::Ready(r),
             IncomingPayload::Forward(_) => unreachable!(),
             IncomingPayload::UpdateState(s) => Self::UpdateState(s),
-            IncomingPayload::Event { guild_id, event } => Self::Event { guild_id, event }
+            IncomingPayload::Event { guild_id, event } => Self::Event {
+                guild_id,
+                event,
+                replayed: value.replayed
+            }
         }
     }
 }
@@ -100,6 +168,35 @@ impl EventForwarder {
             _ => return
         };
 
-        self.sender.send(ToSocketMessage::Send(p)).unwrap();
+        let _ = self.sender.send(ToSocketMessage::Send(p));
+    }
+}
+
+#[cfg(feature = "twilight")]
+/// Delivers the events of the stream to the handler, one after another, until the stream ends.
+pub(crate) async fn dispatch(
+    mut stream: EventStream,
+    handler: &dyn EventHandler,
+    players: &PlayerManager,
+    shared: &Shared
+) {
+    while let Some(event) = stream.next().await {
+        let timeout = shared.config.read().handler_timeout;
+
+        match event {
+            IncomingEvent::Ready(r) => handler.on_ready(r).await,
+            IncomingEvent::Disconnected(reason) => handler.on_disconnect(reason).await,
+            IncomingEvent::ConnectionState(state) => handler.on_connection_state(state).await,
+            IncomingEvent::EventsMissed { first, last } => {
+                handler.on_events_missed(first, last).await
+            },
+            IncomingEvent::UpdateState(state) => {
+                let guild = state.guild_id().get();
+                deliver(handler, players, guild, GuildEvent::UpdateState(state), timeout).await
+            },
+            IncomingEvent::Event { guild_id, event, replayed } => {
+                deliver(handler, players, guild_id, GuildEvent::Event { event, replayed }, timeout).await
+            }
+        }
     }
 }
@@ -3,64 +3,135 @@ pub mod config;
 pub mod error;
 mod socket;
 pub mod player;
+pub mod data;
 pub mod rest;
+pub mod middleware;
+pub mod endpoint;
+pub mod codec;
+pub mod tap;
+pub mod recorder;
 mod msg;
 mod manager;
+mod outbox;
 pub mod source;
 pub mod events;
+pub mod autocomplete;
 #[cfg(feature = "serenity")]
 pub mod serenity_ext;
+mod dispatch;
 
 #[cfg(feature = "twilight")]
-mod stream;
+pub mod stream;
 pub mod reference;
 
+use std::io;
 use std::num::NonZeroU64;
+use std::path::Path;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use futures::Stream;
 use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
 use tokio_tungstenite::tungstenite::Error;
+use tokio::sync::{broadcast, watch};
+use tracing::warn;
+use typemap_rev::TypeMap;
 use uuid::Uuid;
 use socket::Socket;
 use config::Config;
-use crate::error::HttpError;
+use crate::error::{EndpointError, HttpError};
 use crate::manager::PlayerManager;
 use crate::msg::{FromSocketMessage, ToSocketMessage};
 use crate::rest::RestClient;
 use crate::socket::SocketHandle;
 
-#[cfg(feature = "serenity")]
 use crate::events::EventHandler;
 #[cfg(feature = "serenity")]
 use serenity::gateway::VoiceGatewayManager;
 #[cfg(feature = "serenity")]
 use crate::serenity_ext::NightingaleVoiceManager;
 
-use crate::player::Player;
-use crate::source::SearchSource;
+use crate::model::gateway::connection::ConnectionState;
+use crate::model::search::PlaylistPage;
+use crate::player::{Player, PlayerHandle};
+use crate::source::{PagedSearchRoute, SearchSource};
+use crate::autocomplete::{Autocomplete, Suggestion};
 #[cfg(feature = "twilight")]
-use crate::stream::EventStream;
+use crate::stream::{EventStream, GuildEventStream};
 #[cfg(feature = "twilight")]
 use crate::events::EventForwarder;
 #[cfg(feature = "twilight")]
 use twilight_gateway::Shard;
 #[cfg(feature = "twilight")]
 use std::collections::HashMap;
+#[cfg(feature = "twilight")]
+use tokio::task::JoinHandle;
 use crate::config::SessionConfig;
+use crate::endpoint::Endpoint;
+use crate::tap::{RawFrame, Tap};
+use crate::recorder::ReplaySpeed;
 
 use crate::reference::{Reference, ReferenceMut};
 
 pub(crate) struct Shared {
     pub session: RwLock<Uuid>,
+    pub latency: RwLock<Option<Duration>>,
+    pub state: watch::Sender<ConnectionState>,
+    pub dropped_messages: AtomicU64,
+    pub tap: Tap,
+    pub endpoint: Result<Endpoint, EndpointError>,
     pub config: RwLock<Config>,
     pub session_config: RwLock<SessionConfig>
 }
 
+impl Shared {
+    fn new(config: Config) -> Self {
+        Self {
+            session: RwLock::new(Uuid::nil()),
+            latency: RwLock::new(None),
+            state: watch::channel(ConnectionState::Disconnected).0,
+            dropped_messages: AtomicU64::new(0),
+            tap: Tap::new(&config),
+            endpoint: Endpoint::from_config(&config),
+            config: RwLock::new(config),
+            session_config: RwLock::new(SessionConfig::default())
+        }
+    }
+
+    /// Returns the endpoint of the server, or the error returned when parsing it.
+    pub fn endpoint(&self) -> Result<&Endpoint, EndpointError> {
+        self.endpoint.as_ref().map_err(Clone::clone)
+    }
+}
+
+/// What to do with the session and the players when shutting down the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownMode {
+    /// Leaves all voice channels and closes the session.
+    LeaveAll,
+    /// Keeps the players connected and the session alive on the server, so it can be resumed
+    /// after a restart.
+    Persist
+}
+
+/// A session kept alive on the server by [`ShutdownMode::Persist`], which can be resumed using
+/// [`NightingaleClient::resume`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersistedSession {
+    /// The id of the session.
+    pub id: Uuid,
+    /// Sequence number of the last event received, the server replays the events after it.
+    pub seq: Option<u64>
+}
+
 /// Client that handles a single connection to a nightingale server.
 pub struct NightingaleClient {
     socket: SocketHandle,
     http: RestClient,
     shared: Arc<Shared>,
     players: Arc<PlayerManager>,
+    data: Arc<tokio::sync::RwLock<TypeMap>>
 }
 
 impl NightingaleClient {
@@ -69,11 +140,7 @@ impl NightingaleClient {
     pub fn new_serenity(config: Config, handler: impl EventHandler + 'static) -> Self {
         assert_ne!(config.user_id.get(), 1);
         let events = Arc::new(handler) as Arc<dyn EventHandler>;
-        let shared = Arc::new(Shared {
-            session: RwLock::new(Uuid::nil()),
-            config: RwLock::new(config),
-            session_config: RwLock::new(SessionConfig::default())
-        });
+        let shared = Arc::new(Shared::new(config));
         let rest = RestClient::new(shared.clone());
         let players = Arc::new(PlayerManager::new(rest.clone()));
 
@@ -85,7 +152,8 @@ impl NightingaleClient {
             ),
             http: rest,
             shared,
-            players
+            players,
+            data: Arc::new(tokio::sync::RwLock::new(TypeMap::new()))
         }
     }
 
@@ -99,10 +167,7 @@ impl NightingaleClient {
         let map = shards.into_iter().map(|s| (s.id().number(), s.sender()))
             .collect::<HashMap<_, _>>();
 
-        let shared = Arc::new(Shared {
-            session: RwLock::new(Uuid::nil()),
-            config: RwLock::new(config)
-        });
+        let shared = Arc::new(Shared::new(config));
 
         let rest = RestClient::new(shared.clone());
         let players = Arc::new(PlayerManager::new(rest.clone()));
@@ -115,7 +180,8 @@ impl NightingaleClient {
             ),
             http: rest,
             shared,
-            players
+            players,
+            data: Arc::new(tokio::sync::RwLock::new(TypeMap::new()))
         }
     }
 
@@ -134,7 +200,77 @@ impl NightingaleClient {
         &self.http
     }
 
+    /// Returns the type map shared by the whole client, for data that doesn't belong to a single
+    /// player, see [`Player::data`] for per player data.
+    pub fn data(&self) -> &Arc<tokio::sync::RwLock<TypeMap>> {
+        &self.data
+    }
+
+    /// Returns the round-trip latency of the last heartbeat sent to the server, `None` if no
+    /// heartbeat has been acknowledged yet.
+    pub fn latency(&self) -> Option<Duration> {
+        *self.shared.latency.read()
+    }
+
+    /// Returns the number of outgoing messages dropped because the buffer used while disconnected
+    /// was full.
+    pub fn dropped_messages(&self) -> u64 {
+        self.shared.dropped_messages.load(Ordering::Relaxed)
+    }
+
+    /// Returns a receiver of every payload sent to and received from the server, passwords and
+    /// voice tokens are redacted.
+    ///
+    /// Payloads are only captured while there is at least one receiver, so this has no cost
+    /// when unused. Use [`Config::trace_payloads`] to log them instead.
+    pub fn tap(&self) -> broadcast::Receiver<RawFrame> {
+        self.shared.tap.subscribe()
+    }
+
+    /// Replays the incoming gateway payloads of a recording made with a [`Recorder`], as if they
+    /// were received from the server. Returns the number of payloads replayed.
+    ///
+    /// This doesn't require a connection, allowing to reproduce issues without a server. Replayed
+    /// payloads don't change the current session and aren't forwarded to the shards.
+    ///
+    /// [`Recorder`]: crate::recorder::Recorder
+    pub async fn replay(&self, path: impl AsRef<Path>, speed: ReplaySpeed) -> io::Result<usize> {
+        let path = path.as_ref().to_path_buf();
+        let payloads = tokio::task::spawn_blocking(move || recorder::read_incoming(&path, speed))
+            .await
+            .map_err(io::Error::other)??;
+        let mut replayed = 0;
+
+        for (delay, payload) in payloads {
+            if let Some(delay) = delay {
+                tokio::time::sleep(delay).await;
+            }
+
+            let frame = match serde_json::from_value(payload) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    warn!("Skipping recorded payload that couldn't be decoded: {e}");
+                    continue;
+                }
+            };
+
+            let _ = self.socket.sender.send(ToSocketMessage::Replay(Box::new(frame)));
+            replayed += 1;
+        }
+
+        Ok(replayed)
+    }
+
+    /// Returns a receiver that can be used to observe the state of the connection with the
+    /// server.
+    pub fn connection_state(&self) -> watch::Receiver<ConnectionState> {
+        self.shared.state.subscribe()
+    }
+
     async fn connect_reconnect_inner(&mut self, p: ToSocketMessage) -> Result<(), Error> {
+        // Discard results of reconnections made by the socket in the background.
+        while self.socket.receiver.try_recv().is_ok() {}
+
         self.socket.sender.send(p).unwrap();
         while let Some(msg) = self.socket.receiver.recv().await {
             match msg {
@@ -152,9 +288,59 @@ impl NightingaleClient {
         self.connect_reconnect_inner(ToSocketMessage::Connect).await
     }
 
-    /// Disconnects from the server.
+    /// Connects to the server resuming the provided session, like one returned by
+    /// [`NightingaleClient::shutdown`] when using [`ShutdownMode::Persist`].
+    ///
+    /// The server replays the events received after the sequence number of the session, the
+    /// local state of the players is not restored and must be fetched again. If the session
+    /// expired, an error is returned and a new session can be opened using
+    /// [`NightingaleClient::connect`].
+    pub async fn resume(&mut self, session: PersistedSession) -> Result<(), Error> {
+        if session.id == Uuid::nil() {
+            return Err(Error::Io(io::ErrorKind::InvalidInput.into()));
+        }
+
+        self.connect_reconnect_inner(ToSocketMessage::ResumeSession(session)).await
+    }
+
+    /// Disconnects from the server, this also clears the local state of the players.
     pub async fn disconnect(&mut self) {
         self.socket.sender.send(ToSocketMessage::Disconnect).unwrap();
+        self.players.players.clear();
+    }
+
+    /// Shuts down the client, closing the connection with the server and waiting for the
+    /// background task to finish.
+    ///
+    /// When using [`ShutdownMode::Persist`], the session is returned so it can be resumed later
+    /// using [`NightingaleClient::resume`].
+    ///
+    /// Dropping the client without calling this method closes the connection in the background,
+    /// without leaving the voice channels.
+    pub async fn shutdown(mut self, mode: ShutdownMode) -> Option<PersistedSession> {
+        let persist = mode == ShutdownMode::Persist;
+
+        if !persist {
+            let guilds = self.players.players.iter().map(|p| *p.key()).collect::<Vec<_>>();
+
+            for guild in guilds.into_iter().filter_map(NonZeroU64::new) {
+                if let Err(e) = self.http.disconnect(guild).await {
+                    warn!("Failed to leave voice channel in guild {guild}: {e}");
+                }
+            }
+
+            self.players.players.clear();
+        }
+
+        let _ = self.socket.sender.send(ToSocketMessage::Kill { persist });
+
+        let seq = match self.socket.task.take() {
+            Some(task) => task.await.ok().flatten(),
+            None => None
+        };
+
+        let id = *self.shared.session.read();
+        (persist && id != Uuid::nil()).then_some(PersistedSession { id, seq })
     }
 
     /// Reconnects to the server.
@@ -167,11 +353,41 @@ impl NightingaleClient {
     ///
     /// A single instance of the event stream can be present at a time. If called when there is
     /// another stream present, this will return `None`, after dropping the other stream this method
-    /// will return `Some` again
+    /// will return `Some` again.
+    ///
+    /// The stream doesn't borrow the client, so it can be moved into a spawned task.
     pub fn events(&self) -> Option<EventStream> {
         EventStream::new(&self.socket.events)
     }
 
+    #[cfg(feature = "twilight")]
+    /// Returns a stream that only yields the events of the provided guild, received after
+    /// calling this method.
+    ///
+    /// Unlike [`NightingaleClient::events`], any number of guild streams can be active at a time
+    /// and they don't take the events from the main stream, so they can be used to wait for an
+    /// event while the main stream is being consumed somewhere else.
+    pub fn guild_events(&self, guild: impl Into<NonZeroU64>) -> GuildEventStream {
+        GuildEventStream::new(self.socket.guild_events.subscribe(), guild.into().get())
+    }
+
+    #[cfg(feature = "twilight")]
+    /// Delivers the events coming from the server to the provided handler in a background task,
+    /// allowing to share handler code with serenity bots. Events are delivered one after another
+    /// in the order they were received.
+    ///
+    /// This takes the event stream, so it returns `None` if there is another stream present, see
+    /// [`NightingaleClient::events`]. Aborting the returned task gives the stream back.
+    pub fn dispatch_events(&self, handler: impl EventHandler + 'static) -> Option<JoinHandle<()>> {
+        let stream = self.events()?;
+        let players = Arc::clone(&self.players);
+        let shared = Arc::clone(&self.shared);
+
+        Some(tokio::spawn(async move {
+            events::dispatch(stream, &handler, &players, &shared).await;
+        }))
+    }
+
     #[cfg(feature = "twilight")]
     /// Returns a forwarder that must be used to forward voice server update and voice state update
     /// events, this will only send the minimum required fields in the payload, not the whole event.
@@ -188,22 +404,45 @@ impl NightingaleClient {
         G: Into<NonZeroU64>,
         C: Into<NonZeroU64>
     {
-        let guild = guild.into();
-        self.http.connect(guild.into(), channel.into()).await
-            .map(|res| {
+        self.join_inner(&self.http, guild.into(), channel.into()).await
+    }
+
+    /// Joins the given voice channel, failing with [`HttpError::Timeout`] if the server
+    /// doesn't answer within the provided timeout.
+    pub async fn join_with_timeout<G, C>(&self, guild: G, channel: C, timeout: Duration)
+        -> Result<(), HttpError>
+    where
+        G: Into<NonZeroU64>,
+        C: Into<NonZeroU64>
+    {
+        self.join_inner(&self.http.with_timeout(timeout), guild.into(), channel.into()).await
+    }
+
+    async fn join_inner(&self, http: &RestClient, guild: NonZeroU64, channel: NonZeroU64)
+        -> Result<(), HttpError> {
+        http.connect(guild, channel).await
+            .inspect(|_| {
                 self.players.get_or_insert(guild.get());
-                res
             })
     }
 
     /// Leaves the given voice channel.
     pub async fn leave<G: Into<NonZeroU64>>(&self, guild: G)
         -> Result<(), HttpError> {
-        let guild = guild.into();
-        self.http.disconnect(guild).await
-            .map(|res| {
+        self.leave_inner(&self.http, guild.into()).await
+    }
+
+    /// Leaves the given voice channel, failing with [`HttpError::Timeout`] if the server
+    /// doesn't answer within the provided timeout.
+    pub async fn leave_with_timeout<G: Into<NonZeroU64>>(&self, guild: G, timeout: Duration)
+        -> Result<(), HttpError> {
+        self.leave_inner(&self.http.with_timeout(timeout), guild.into()).await
+    }
+
+    async fn leave_inner(&self, http: &RestClient, guild: NonZeroU64) -> Result<(), HttpError> {
+        http.disconnect(guild).await
+            .inspect(|_| {
                 self.players.players.remove(&guild.get());
-                res
             })
     }
 
@@ -223,6 +462,57 @@ impl NightingaleClient {
         self.http.playlist(playlist, source).await
     }
 
+    /// Gets a single page of the playlist items from the specified source, see
+    /// [`RestClient::playlist_page`] for more information.
+    pub async fn playlist_page<S>(
+        &self,
+        playlist: String,
+        continuation: Option<String>,
+        source: S
+    ) -> Result<PlaylistPage<S::Track>, HttpError>
+    where
+        S: SearchSource + PagedSearchRoute
+    {
+        self.http.playlist_page(playlist, continuation, source).await
+    }
+
+    /// Returns a stream that lazily yields the playlist items from the specified source, see
+    /// [`RestClient::playlist_stream`] for more information.
+    pub fn playlist_stream<S>(
+        &self,
+        playlist: String,
+        source: S
+    ) -> impl Stream<Item = Result<S::Track, HttpError>>
+    where
+        S: SearchSource + PagedSearchRoute
+    {
+        self.http.playlist_stream(playlist, source)
+    }
+
+    /// Returns an autocomplete helper that makes debounced searches on the provided source.
+    pub fn autocomplete<S>(&self, source: S) -> Autocomplete<S>
+    where
+        S: SearchSource + Clone,
+        S::Track: Suggestion
+    {
+        Autocomplete::new(self.http.clone(), source)
+    }
+
+    /// Fetches the players of the current session from the server and updates the local players
+    /// to match them. Local players not present on the server are removed.
+    pub async fn sync_players(&self) -> Result<(), HttpError> {
+        let players = self.http.players().await?;
+        self.players.sync(players);
+
+        Ok(())
+    }
+
+    /// Returns a handle to the player of the provided guild, which can be held across awaits
+    /// without blocking other players, prefer it over [`NightingaleClient::get_player_mut`].
+    pub fn player(&self, guild: impl Into<NonZeroU64>) -> PlayerHandle {
+        PlayerHandle::new(self.http.clone(), Arc::clone(&self.players), guild.into())
+    }
+
     /// Returns a reference to the player of the provided guild, if present.
     pub fn get_player(&self, guild: impl Into<NonZeroU64>) -> Option<Reference<Player>> {
         self.players.players.get(&guild.into().get())
@@ -230,8 +520,18 @@ impl NightingaleClient {
     }
 
     /// Returns a mutable reference to the player of the provided guild, if present.
+    ///
+    /// The player stays locked until the reference is dropped, which also blocks other players,
+    /// so the reference must not be held across awaits. Use [`NightingaleClient::player`] instead
+    /// to make requests.
     pub fn get_player_mut(&self, guild: impl Into<NonZeroU64>) -> Option<ReferenceMut<Player>> {
         self.players.players.get_mut(&guild.into().get())
             .map(Into::into)
     }
 }
+
+impl Drop for NightingaleClient {
+    fn drop(&mut self) {
+        let _ = self.socket.sender.send(ToSocketMessage::Kill { persist: false });
+    }
+}
@@ -1,6 +1,7 @@
 use std::num::NonZeroU64;
 use dashmap::DashMap;
 use dashmap::mapref::one::{Ref, RefMut};
+use crate::model::player::PlayerInfo;
 use crate::player::Player;
 use crate::rest::RestClient;
 
@@ -17,23 +18,25 @@ impl PlayerManager {
         }
     }
 
-    pub fn get_or_insert(&self, guild: u64) -> Ref<u64, Player> {
-        if self.players.contains_key(&guild) {
-            self.players.get(&guild).unwrap()
-        } else {
-            let player = Player::new(self.http.clone(), NonZeroU64::new(guild).unwrap());
-            self.players.insert(guild, player);
-            self.players.get(&guild).unwrap()
-        }
+    pub fn get_or_insert(&self, guild: u64) -> Ref<'_, u64, Player> {
+        self.get_or_insert_mut(guild).downgrade()
+    }
+
+    /// Returns the player of the guild, creating it if it doesn't exist. The lookup and the
+    /// insertion happen under the same lock, so concurrent callers always get the same player.
+    pub fn get_or_insert_mut(&self, guild: u64) -> RefMut<'_, u64, Player> {
+        self.players.entry(guild)
+            .or_insert_with(|| Player::new(self.http.clone(), NonZeroU64::new(guild).unwrap()))
     }
 
-    pub fn get_or_insert_mut(&self, guild: u64) -> RefMut<u64, Player> {
-        if self.players.contains_key(&guild) {
-            self.players.get_mut(&guild).unwrap()
-        } else {
-            let player = Player::new(self.http.clone(), NonZeroU64::new(guild).unwrap());
-            self.players.insert(guild, player);
-            self.players.get_mut(&guild).unwrap()
+    /// Replaces the local state of the players with the provided one, removing the players that
+    /// are not present.
+    pub fn sync(&self, players: Vec<PlayerInfo>) {
+        let present = players.iter().map(|p| p.guild_id.get()).collect::<Vec<_>>();
+        self.players.retain(|guild, _| present.contains(guild));
+
+        for info in players {
+            self.get_or_insert_mut(info.guild_id.get()).update(info);
         }
     }
-}
\ No newline at end of file
+}
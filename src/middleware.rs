@@ -0,0 +1,56 @@
+use std::num::NonZeroU64;
+use std::time::Duration;
+use reqwest::Request;
+use crate::error::HttpError;
+use crate::rest::HttpResponse;
+
+/// Information about the route a request is made to.
+#[derive(Debug, Clone, Copy)]
+pub struct RouteInfo {
+    /// Name of the route, like `search` or `player_play`.
+    pub name: &'static str,
+    /// The guild the request refers to, if any.
+    pub guild: Option<NonZeroU64>
+}
+
+impl RouteInfo {
+    pub(crate) fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            guild: None
+        }
+    }
+
+    pub(crate) fn guild(name: &'static str, guild: NonZeroU64) -> Self {
+        Self {
+            name,
+            guild: Some(guild)
+        }
+    }
+}
+
+/// Hooks executed around every request made by the [`RestClient`], registered using
+/// [`Config::middleware`]. Middlewares are executed in the order they were registered.
+///
+/// Hooks are executed once per attempt, so a request that gets retried will call them multiple
+/// times.
+///
+/// [`RestClient`]: crate::rest::RestClient
+/// [`Config::middleware`]: crate::config::Config::middleware
+pub trait Middleware: Send + Sync {
+    /// Called before the request is sent, allowing to modify it, for example to add custom
+    /// headers.
+    fn before_request(&self, _route: &RouteInfo, _request: &mut Request) {}
+    /// Called after the request completes, with the response or the error returned and the
+    /// time it took to complete.
+    ///
+    /// The error is the same [`HttpError`] returned to the caller, so failures of the unix
+    /// socket transport are reported as [`HttpError::Transport`] and timeouts as
+    /// [`HttpError::Timeout`] regardless of the transport used.
+    fn after_response(
+        &self,
+        _route: &RouteInfo,
+        _response: Result<&HttpResponse, &HttpError>,
+        _elapsed: Duration
+    ) {}
+}
@@ -1,10 +1,70 @@
+use reqwest::StatusCode;
 use serde::Deserialize;
+use serde_json::Value;
 use thiserror::Error;
 
+/// Machine-readable code identifying the kind of error returned by the server.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The player for the requested guild does not exist.
+    PlayerNotFound,
+    /// The session does not exist or has expired.
+    SessionNotFound,
+    /// The player is not connected to a voice channel.
+    NotConnected,
+    /// The provided source could not be played.
+    InvalidSource,
+    /// The queue of the player is full.
+    QueueFull,
+    /// The provided credentials were rejected.
+    Unauthorized,
+    /// Too many requests were made in a short period of time.
+    RateLimited,
+    /// The server encountered an internal error.
+    Internal,
+    /// The code is unknown to this client or the server didn't provide one.
+    #[default]
+    #[serde(other)]
+    Unknown
+}
+
 #[derive(Deserialize, Debug, Error)]
 #[error("Server responded with an error: {message}")]
 pub struct ErrorResponse {
-    pub message: String
+    /// The code of the error.
+    #[serde(default)]
+    pub code: ErrorCode,
+    /// Human-readable description of the error.
+    pub message: String,
+    /// Additional details about the error, if provided.
+    #[serde(default)]
+    pub details: Option<Value>,
+    /// The status code of the response containing the error.
+    #[serde(skip)]
+    pub status: Option<StatusCode>
 }
 
+impl ErrorResponse {
+    /// Returns whether the error indicates the requested resource does not exist.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self.code, ErrorCode::PlayerNotFound | ErrorCode::SessionNotFound)
+            || self.status == Some(StatusCode::NOT_FOUND)
+    }
 
+    /// Returns whether making the same request again could succeed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self.code, ErrorCode::RateLimited | ErrorCode::Internal)
+            || self.status.is_some_and(is_retryable_status)
+    }
+}
+
+pub(crate) fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
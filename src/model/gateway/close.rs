@@ -0,0 +1,33 @@
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+
+/// The reason the connection with the server was closed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisconnectReason {
+    /// The close code sent by the server.
+    pub code: u16,
+    /// The close reason sent by the server, may be empty.
+    pub reason: String
+}
+
+impl DisconnectReason {
+    /// Returns whether the server closed the connection normally, like when shutting down.
+    pub fn is_normal(&self) -> bool {
+        self.code == 1000 || self.code == 1001
+    }
+
+    /// Returns whether the connection was closed abnormally and the client should try to
+    /// reconnect, this excludes normal closures and the `4xxx` codes used by the server to
+    /// reject the client.
+    pub fn should_reconnect(&self) -> bool {
+        !self.is_normal() && !(4000..5000).contains(&self.code)
+    }
+}
+
+impl From<CloseFrame<'_>> for DisconnectReason {
+    fn from(value: CloseFrame<'_>) -> Self {
+        Self {
+            code: value.code.into(),
+            reason: value.reason.into_owned()
+        }
+    }
+}
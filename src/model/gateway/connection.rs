@@ -0,0 +1,22 @@
+/// The state of the connection with the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// There is no connection with the server, either because it was never opened, it was closed
+    /// or it was lost.
+    Disconnected,
+    /// A new session is being opened.
+    Connecting,
+    /// The connection is open and events are being received.
+    Connected,
+    /// The connection was lost and the previous session is being resumed.
+    Resuming,
+    /// All connection attempts failed, a new one must be started manually.
+    Failed
+}
+
+impl ConnectionState {
+    /// Returns whether the connection is open.
+    pub fn is_connected(&self) -> bool {
+        matches!(self, Self::Connected)
+    }
+}
@@ -3,7 +3,7 @@ use serde::Deserialize;
 use crate::model::track::Track;
 
 /// Track related events received from the gateway.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(tag = "type", content = "data")]
 #[serde(rename_all = "snake_case")]
 pub enum Event {
@@ -16,7 +16,7 @@ pub enum Event {
 }
 
 /// Event fired when a track had an error.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct TrackErrored {
     /// The error that occurred.
     pub error: String,
@@ -25,7 +25,7 @@ pub struct TrackErrored {
 }
 
 /// Event fired when a track finishes its playback.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct TrackEnd {
     /// Whether if the track was stopped manually.
     pub stopped: bool,
@@ -1,3 +1,5 @@
+pub mod close;
+pub mod connection;
 pub mod event;
 pub mod forward;
 pub mod ready;
@@ -17,4 +19,17 @@ pub(crate) enum IncomingPayload {
         guild_id: u64,
         event: event::Event
     }
-}
\ No newline at end of file
+}
+
+/// A payload received from the server along with its sequence information.
+#[derive(Deserialize)]
+pub(crate) struct IncomingFrame {
+    /// Sequence number of the payload, only present for payloads that can be replayed.
+    #[serde(default)]
+    pub seq: Option<u64>,
+    /// Whether the payload is being replayed after resuming a session.
+    #[serde(default)]
+    pub replayed: bool,
+    #[serde(flatten)]
+    pub payload: IncomingPayload
+}
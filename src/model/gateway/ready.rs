@@ -4,7 +4,7 @@ use crate::model::player::PlayerInfo;
 
 /// The ready event, fired when a new connection is established
 /// with the server.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Ready {
     /// Whether if the session was resumed or not.
     pub resumed: bool,
@@ -3,7 +3,7 @@ use std::num::NonZeroU64;
 use serde::Deserialize;
 
 /// Voice update state related events.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(tag = "type", content = "data")]
 #[serde(rename_all = "snake_case")]
 pub enum UpdateState {
@@ -16,8 +16,18 @@ pub enum UpdateState {
     DisconnectGateway(DisconnectData)
 }
 
+impl UpdateState {
+    /// Returns the id of the guild the update belongs to.
+    pub fn guild_id(&self) -> NonZeroU64 {
+        match self {
+            Self::ConnectGateway(data) | Self::ReconnectGateway(data) => data.guild_id,
+            Self::DisconnectGateway(data) => data.guild_id
+        }
+    }
+}
+
 /// The data about the connection
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct ConnectionData {
     /// Channel id the server is connected to.
     pub channel_id: Option<NonZeroU64>,
@@ -31,7 +41,7 @@ pub struct ConnectionData {
     pub ssrc: u32
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct DisconnectData {
     /// The channel id the server disconnected from.
     pub channel_id: Option<NonZeroU64>,
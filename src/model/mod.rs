@@ -3,4 +3,5 @@ pub mod track;
 pub mod error;
 pub mod search;
 pub mod info;
-pub mod player;
\ No newline at end of file
+pub mod player;
+pub mod session;
\ No newline at end of file
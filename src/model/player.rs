@@ -3,7 +3,7 @@ use serde::Deserialize;
 use crate::model::track::Track;
 
 /// Serializable player object returned from the player info route.
-#[derive(Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct PlayerInfo {
     pub guild_id: NonZeroU64,
     pub channel_id: Option<NonZeroU64>,
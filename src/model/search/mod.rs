@@ -1 +1,14 @@
-pub mod youtube;
\ No newline at end of file
+pub mod youtube;
+
+use serde::Deserialize;
+
+/// A single page of a playlist, returned from paginated playlist calls.
+#[derive(Deserialize)]
+pub struct PlaylistPage<T> {
+    /// Name of the playlist.
+    pub name: String,
+    /// Tracks contained in this page.
+    pub tracks: Vec<T>,
+    /// Token used to request the next page, `None` if this is the last one.
+    pub continuation: Option<String>
+}
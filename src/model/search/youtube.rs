@@ -1,4 +1,6 @@
+use std::hash::{Hash, Hasher};
 use serde::Deserialize;
+use crate::model::track::TrackId;
 
 #[derive(Deserialize)]
 pub struct YoutubePlaylist {
@@ -8,7 +10,7 @@ pub struct YoutubePlaylist {
     pub tracks: Vec<YoutubeTrack>
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct YoutubeTrack {
     /// Title of the track.
     pub title: String,
@@ -25,3 +27,25 @@ pub struct YoutubeTrack {
     /// The thumbnail of the video.
     pub thumbnail: String
 }
+
+impl YoutubeTrack {
+    /// Returns the identifier of the track.
+    pub fn id(&self) -> TrackId {
+        TrackId::Youtube(self.video_id.clone())
+    }
+}
+
+/// Two tracks are equal if their video ids are.
+impl PartialEq for YoutubeTrack {
+    fn eq(&self, other: &Self) -> bool {
+        self.video_id == other.video_id
+    }
+}
+
+impl Eq for YoutubeTrack {}
+
+impl Hash for YoutubeTrack {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.video_id.hash(state);
+    }
+}
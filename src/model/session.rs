@@ -0,0 +1,14 @@
+use serde::Deserialize;
+use uuid::Uuid;
+use crate::model::info::PlaybackInfo;
+
+/// Information about a session, returned from the sessions routes.
+#[derive(Debug, Deserialize)]
+pub struct SessionInfo {
+    /// The id of the session.
+    pub id: Uuid,
+    /// Whether a client is currently connected to the session.
+    pub connected: bool,
+    /// Playback information of the players of the session.
+    pub playback: PlaybackInfo
+}
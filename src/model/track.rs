@@ -1,20 +1,211 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 use std::time::Duration;
 
-use serde::{Deserialize, Deserializer};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use url::Url;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use crate::error::TrackIdError;
+use crate::source::{Link, PlaySource};
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Track {
     pub track: Option<String>,
     pub artist: Option<String>,
     pub album: Option<String>,
     pub channel: Option<String>,
-    #[serde(deserialize_with = "duration_from_millis")]
+    #[serde(deserialize_with = "duration_from_millis", serialize_with = "duration_to_millis")]
     pub duration: Option<Duration>,
     pub source_url: Option<String>,
     pub title: Option<String>,
     pub thumbnail: Option<String>
 }
 
+impl Track {
+    /// Returns the identifier of the track, `None` if the server didn't provide the url
+    /// the track was loaded from.
+    pub fn id(&self) -> Option<TrackId> {
+        self.source_url.as_deref().map(TrackId::from_url)
+    }
+}
+
+/// Two tracks are equal if their identifiers are. Tracks without identifier are compared field by
+/// field.
+impl PartialEq for Track {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.id(), other.id()) {
+            (Some(this), Some(other)) => this == other,
+            (None, None) => {
+                self.track == other.track
+                    && self.artist == other.artist
+                    && self.album == other.album
+                    && self.channel == other.channel
+                    && self.duration == other.duration
+                    && self.title == other.title
+                    && self.thumbnail == other.thumbnail
+            },
+            _ => false
+        }
+    }
+}
+
+impl Eq for Track {}
+
+impl Hash for Track {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self.id() {
+            Some(id) => id.hash(state),
+            None => {
+                self.track.hash(state);
+                self.artist.hash(state);
+                self.album.hash(state);
+                self.channel.hash(state);
+                self.duration.hash(state);
+                self.title.hash(state);
+                self.thumbnail.hash(state);
+            }
+        }
+    }
+}
+
+/// Compact and URL-safe identifier of a track.
+///
+/// The encoded form, returned by [`TrackId::encode`] and the [`Display`] implementation, can be
+/// stored in databases or used inside component custom ids, and decoded back using
+/// [`TrackId::decode`] or [`FromStr`]. The identifier can be played directly since it implements
+/// [`PlaySource`].
+///
+/// [`Display`]: fmt::Display
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TrackId {
+    /// A youtube video, identified by its video id.
+    Youtube(String),
+    /// A track identified by the url it was loaded from.
+    Link(String)
+}
+
+impl TrackId {
+    const YOUTUBE: &'static str = "y";
+    const LINK: &'static str = "l";
+
+    /// Creates an identifier from the url a track was loaded from, youtube urls are recognized
+    /// and converted into [`TrackId::Youtube`].
+    pub fn from_url(url: &str) -> Self {
+        youtube_video_id(url)
+            .map(Self::Youtube)
+            .unwrap_or_else(|| Self::Link(url.to_string()))
+    }
+
+    /// Returns the url the track can be played from.
+    pub fn url(&self) -> String {
+        match self {
+            Self::Youtube(id) => format!("https://www.youtube.com/watch?v={id}"),
+            Self::Link(url) => url.clone()
+        }
+    }
+
+    /// Encodes the identifier into its compact form.
+    pub fn encode(&self) -> String {
+        match self {
+            Self::Youtube(id) => format!("{}.{id}", Self::YOUTUBE),
+            Self::Link(url) => format!("{}.{}", Self::LINK, URL_SAFE_NO_PAD.encode(url))
+        }
+    }
+
+    /// Decodes an identifier previously encoded with [`TrackId::encode`].
+    pub fn decode(encoded: &str) -> Result<Self, TrackIdError> {
+        let (kind, data) = encoded.split_once('.').ok_or(TrackIdError::Malformed)?;
+
+        if data.is_empty() {
+            return Err(TrackIdError::Malformed);
+        }
+
+        match kind {
+            Self::YOUTUBE => {
+                if !is_youtube_id(data) {
+                    return Err(TrackIdError::InvalidEncoding);
+                }
+
+                Ok(Self::Youtube(data.to_string()))
+            },
+            Self::LINK => {
+                let bytes = URL_SAFE_NO_PAD.decode(data)
+                    .map_err(|_| TrackIdError::InvalidEncoding)?;
+
+                String::from_utf8(bytes)
+                    .map(Self::Link)
+                    .map_err(|_| TrackIdError::InvalidEncoding)
+            },
+            other => Err(TrackIdError::UnknownKind(other.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for TrackId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.encode())
+    }
+}
+
+impl FromStr for TrackId {
+    type Err = TrackIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::decode(s)
+    }
+}
+
+impl Serialize for TrackId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.encode())
+    }
+}
+
+impl<'de> Deserialize<'de> for TrackId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = <String as Deserialize>::deserialize(deserializer)?;
+        Self::decode(&encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+impl PlaySource for TrackId {
+    fn value_for(self) -> Value {
+        Link(self.url()).value_for()
+    }
+}
+
+fn youtube_video_id(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+
+    let id = match url.host_str()? {
+        "youtube.com" | "www.youtube.com" | "m.youtube.com" | "music.youtube.com" => {
+            url.query_pairs()
+                .find(|(key, _)| key == "v")
+                .map(|(_, value)| value.into_owned())?
+        },
+        "youtu.be" => url.path_segments()?.next()?.to_string(),
+        _ => return None
+    };
+
+    is_youtube_id(&id).then_some(id)
+}
+
+/// Returns whether `id` only contains the characters youtube uses in video ids, ids with other
+/// characters are stored as links so they can be encoded safely.
+fn is_youtube_id(id: &str) -> bool {
+    !id.is_empty() && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+fn duration_to_millis<S>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer
+{
+    duration.map(|d| d.as_millis() as u64).serialize(serializer)
+}
+
 fn duration_from_millis<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
 where
     D: Deserializer<'de>
@@ -22,3 +213,72 @@ where
     Ok(<Option<u128> as Deserialize>::deserialize(deserializer)?
         .map(|millis| Duration::from_millis(millis as _)))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn youtube_urls() {
+        let urls = [
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+            "https://youtube.com/watch?list=PL123&v=dQw4w9WgXcQ",
+            "https://m.youtube.com/watch?v=dQw4w9WgXcQ",
+            "https://music.youtube.com/watch?v=dQw4w9WgXcQ",
+            "https://youtu.be/dQw4w9WgXcQ",
+            "https://youtu.be/dQw4w9WgXcQ?t=42"
+        ];
+
+        for url in urls {
+            assert_eq!(TrackId::from_url(url), TrackId::Youtube(String::from("dQw4w9WgXcQ")), "{url}");
+        }
+    }
+
+    #[test]
+    fn invalid_youtube_ids_fall_back_to_links() {
+        let urls = [
+            "https://www.youtube.com/watch?v=a.b",
+            "https://www.youtube.com/watch?v=a%20b",
+            "https://www.youtube.com/watch?v=",
+            "https://www.youtube.com/watch",
+            "https://youtu.be/"
+        ];
+
+        for url in urls {
+            assert_eq!(TrackId::from_url(url), TrackId::Link(url.to_string()), "{url}");
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let ids = [
+            TrackId::from_url("https://www.youtube.com/watch?v=dQw4w9WgXcQ"),
+            TrackId::from_url("https://youtu.be/a-b_c"),
+            TrackId::from_url("https://www.youtube.com/watch?v=a.b"),
+            TrackId::from_url("https://example.com/track.mp3?query=a b&other=ñ")
+        ];
+
+        for id in ids {
+            let encoded = id.encode();
+            assert_eq!(TrackId::decode(&encoded).unwrap(), id, "{encoded}");
+            assert_eq!(encoded.parse::<TrackId>().unwrap(), id);
+        }
+    }
+
+    #[test]
+    fn encoded_form() {
+        assert_eq!(TrackId::Youtube(String::from("dQw4w9WgXcQ")).encode(), "y.dQw4w9WgXcQ");
+        assert_eq!(TrackId::Link(String::from("a")).encode(), "l.YQ");
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(matches!(TrackId::decode(""), Err(TrackIdError::Malformed)));
+        assert!(matches!(TrackId::decode("y"), Err(TrackIdError::Malformed)));
+        assert!(matches!(TrackId::decode("y."), Err(TrackIdError::Malformed)));
+        assert!(matches!(TrackId::decode("y.a.b"), Err(TrackIdError::InvalidEncoding)));
+        assert!(matches!(TrackId::decode("l.***"), Err(TrackIdError::InvalidEncoding)));
+        assert!(matches!(TrackId::decode("l.__8"), Err(TrackIdError::InvalidEncoding)));
+        assert!(matches!(TrackId::decode("x.abc"), Err(TrackIdError::UnknownKind(k)) if k == "x"));
+    }
+}
@@ -1,5 +1,7 @@
 use futures::channel::mpsc::UnboundedSender;
 use serde_json::Value;
+use crate::model::gateway::IncomingFrame;
+use crate::PersistedSession;
 
 #[cfg(feature = "serenity")]
 use serenity::all::ShardRunnerMessage;
@@ -9,12 +11,20 @@ pub(crate) enum ToSocketMessage {
     Disconnect,
     Reconnect,
     Resume,
+    /// Resumes the provided session instead of the current one.
+    ResumeSession(PersistedSession),
     Send(Value),
+    /// Handles a recorded payload as if it was received from the server.
+    Replay(Box<IncomingFrame>),
     #[cfg(feature = "serenity")]
     RegisterShard(u32, UnboundedSender<ShardRunnerMessage>),
     #[cfg(feature = "serenity")]
     DeregisterShard(u32),
-    Kill
+    /// Closes the connection and stops the socket task, `persist` keeps the session alive on the
+    /// server so it can be resumed later.
+    Kill {
+        persist: bool
+    }
 }
 
 pub(crate) enum FromSocketMessage {
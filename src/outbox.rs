@@ -0,0 +1,123 @@
+use std::collections::VecDeque;
+use serde_json::Value;
+
+/// Outgoing payloads waiting for the connection to be established.
+///
+/// Only the latest payload is kept for every guild and op, as older voice updates are superseded
+/// by newer ones.
+#[derive(Default)]
+pub(crate) struct Outbox {
+    queue: VecDeque<(Option<(u64, String)>, Value)>
+}
+
+impl Outbox {
+    /// Queues a payload, replacing the previous one with the same guild and op. Returns the number
+    /// of payloads dropped to respect the limit.
+    pub fn push(&mut self, payload: Value, limit: usize) -> usize {
+        let key = key(&payload);
+
+        if key.is_some() {
+            self.queue.retain(|(k, _)| *k != key);
+        }
+
+        self.queue.push_back((key, payload));
+
+        let dropped = self.queue.len().saturating_sub(limit);
+        self.queue.drain(..dropped);
+
+        dropped
+    }
+
+    /// Puts back a payload that couldn't be sent, keeping it at the front of the queue.
+    pub fn requeue(&mut self, payload: Value) {
+        self.queue.push_front((key(&payload), payload));
+    }
+
+    pub fn pop(&mut self) -> Option<Value> {
+        self.queue.pop_front().map(|(_, payload)| payload)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+fn key(payload: &Value) -> Option<(u64, String)> {
+    let op = payload.get("op")?.as_str()?;
+    let guild = payload.get("data")?.get("guild_id")?.as_u64()?;
+
+    Some((guild, op.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn payload(op: &str, guild: u64, session: &str) -> Value {
+        json!({
+            "op": op,
+            "data": {
+                "guild_id": guild,
+                "session_id": session
+            }
+        })
+    }
+
+    fn drain(outbox: &mut Outbox) -> Vec<Value> {
+        std::iter::from_fn(|| outbox.pop()).collect()
+    }
+
+    #[test]
+    fn replaces_same_guild_and_op() {
+        let mut outbox = Outbox::default();
+
+        assert_eq!(outbox.push(payload("update_voice_state", 1, "a"), 10), 0);
+        assert_eq!(outbox.push(payload("update_voice_server", 1, "a"), 10), 0);
+        assert_eq!(outbox.push(payload("update_voice_state", 2, "a"), 10), 0);
+        assert_eq!(outbox.push(payload("update_voice_state", 1, "b"), 10), 0);
+
+        assert_eq!(drain(&mut outbox), vec![
+            payload("update_voice_server", 1, "a"),
+            payload("update_voice_state", 2, "a"),
+            payload("update_voice_state", 1, "b")
+        ]);
+        assert!(outbox.is_empty());
+    }
+
+    #[test]
+    fn keeps_payloads_without_key() {
+        let mut outbox = Outbox::default();
+
+        outbox.push(json!({ "op": "ping" }), 10);
+        outbox.push(json!({ "op": "ping" }), 10);
+
+        assert_eq!(drain(&mut outbox).len(), 2);
+    }
+
+    #[test]
+    fn drops_oldest_over_limit() {
+        let mut outbox = Outbox::default();
+
+        assert_eq!(outbox.push(payload("update_voice_state", 1, "a"), 2), 0);
+        assert_eq!(outbox.push(payload("update_voice_state", 2, "a"), 2), 0);
+        assert_eq!(outbox.push(payload("update_voice_state", 3, "a"), 2), 1);
+
+        assert_eq!(drain(&mut outbox), vec![
+            payload("update_voice_state", 2, "a"),
+            payload("update_voice_state", 3, "a")
+        ]);
+    }
+
+    #[test]
+    fn requeue_goes_first() {
+        let mut outbox = Outbox::default();
+
+        outbox.push(payload("update_voice_state", 1, "a"), 10);
+        outbox.requeue(payload("update_voice_server", 2, "a"));
+
+        assert_eq!(outbox.pop(), Some(payload("update_voice_server", 2, "a")));
+        assert_eq!(outbox.pop(), Some(payload("update_voice_state", 1, "a")));
+        assert_eq!(outbox.pop(), None);
+    }
+}
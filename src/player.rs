@@ -1,19 +1,119 @@
+use std::collections::HashMap;
 use std::num::NonZeroU64;
-use typemap_rev::TypeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+use crate::data::PlayerData;
 use crate::error::HttpError;
+use crate::manager::PlayerManager;
 use crate::model::player::PlayerInfo;
 use crate::model::track::Track;
 use crate::rest::RestClient;
 use crate::source::PlaySource;
 
+/// Options used when playing a track, see [`Player::enqueue_with`] and
+/// [`Player::force_play_with`].
+#[derive(Debug, Clone, Default)]
+pub struct PlayOptions {
+    pub(crate) start: Option<Duration>,
+    pub(crate) end: Option<Duration>,
+    pub(crate) volume: Option<u8>,
+    pub(crate) paused: bool,
+    pub(crate) no_replace: bool
+}
+
+impl PlayOptions {
+    /// Creates options with the default values.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts the playback at the provided offset, like the timestamp of a link.
+    pub fn start(mut self, start: Duration) -> Self {
+        self.start = Some(start);
+        self
+    }
+
+    /// Stops the playback once the provided time of the track is reached, useful for clips. Must
+    /// be after the start, if any.
+    pub fn end(mut self, end: Duration) -> Self {
+        self.end = Some(end);
+        self
+    }
+
+    /// Sets the volume the track starts playing with.
+    pub fn volume(mut self, volume: u8) -> Self {
+        self.volume = Some(volume);
+        self
+    }
+
+    /// Whether the track starts paused, the default value is `false`.
+    pub fn paused(mut self, paused: bool) -> Self {
+        self.paused = paused;
+        self
+    }
+
+    /// Whether the track is discarded if something is already playing, the default value is
+    /// `false`. It can't be used when forcing a track to play, as that always replaces the
+    /// current one.
+    ///
+    /// The server decides whether the track is discarded, so the local state of the player is
+    /// fetched again after playing with this option.
+    pub fn no_replace(mut self, no_replace: bool) -> Self {
+        self.no_replace = no_replace;
+        self
+    }
+
+    /// Checks that the options don't contradict each other.
+    pub(crate) fn validate(&self, force: bool) -> Result<(), HttpError> {
+        if let (Some(start), Some(end)) = (self.start, self.end) {
+            if end <= start {
+                return Err(HttpError::InvalidOptions("the end must be after the start"));
+            }
+        }
+
+        if force && self.no_replace {
+            return Err(HttpError::InvalidOptions("no_replace can't be used when forcing a track"));
+        }
+
+        Ok(())
+    }
+
+    /// Updates the local state of the player after playing the provided track, without asking
+    /// the server.
+    fn apply(&self, player: &mut Player, track: Track, force: bool) {
+        if !self.no_replace {
+            if force {
+                player.queue.insert(0, track);
+            } else {
+                player.queue.push(track);
+            }
+        }
+
+        if self.paused {
+            player.paused = true;
+        }
+
+        if let Some(volume) = self.volume {
+            player.volume = volume;
+        }
+    }
+}
+
 /// A player assigned to a guild.
+///
+/// Cloning a player creates a copy of its state, the data returned by [`Player::data`] is
+/// shared between the copies.
+#[derive(Clone)]
 pub struct Player {
     http: RestClient,
     queue: Vec<Track>,
     current: Option<Track>,
     paused: bool,
     volume: u8,
-    data: TypeMap,
+    data: Arc<PlayerData>,
     guild: NonZeroU64
 }
 
@@ -23,18 +123,39 @@ impl Player {
             http,
             queue: Vec::new(),
             current: None,
-            data: TypeMap::new(),
+            data: Arc::new(PlayerData::default()),
             guild,
             paused: false,
             volume: 100
         }
     }
 
-    /// Returns the inner type map held by the player.
-    pub fn data(&self) -> &TypeMap {
+    /// Updates the local state of the player using the information held by the server.
+    pub(crate) fn update(&mut self, info: PlayerInfo) {
+        self.queue = info.queue;
+        self.current = info.currently_playing;
+        self.paused = info.paused;
+        self.volume = info.volume;
+    }
+
+    /// Returns the custom data attached to the player.
+    pub fn data(&self) -> &PlayerData {
         &self.data
     }
 
+    /// Returns a serializable snapshot of the state of the player, including the data stored
+    /// using [`PlayerData::insert_persistent`].
+    pub fn snapshot(&self) -> PlayerSnapshot {
+        PlayerSnapshot {
+            guild_id: self.guild,
+            queue: self.queue.clone(),
+            current: self.current.clone(),
+            paused: self.paused,
+            volume: self.volume,
+            data: self.data.persistent_values()
+        }
+    }
+
     /// Returns the track that is currently being played, if someone.
     pub fn current(&self) -> &Option<Track> {
         &self.current
@@ -45,6 +166,21 @@ impl Player {
         &self.queue
     }
 
+    /// Returns whether the playback is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Returns the volume of the player.
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    /// Returns the id of the guild the player belongs to.
+    pub fn guild(&self) -> NonZeroU64 {
+        self.guild
+    }
+
     /// Gets the information held by the server about the player.
     pub async fn info(&self) -> Result<PlayerInfo, HttpError> {
         self.http.player_info(self.guild).await
@@ -52,16 +188,47 @@ impl Player {
 
     /// Enqueues the provided track to be played.
     pub async fn enqueue(&mut self, source: impl PlaySource) -> Result<Track, HttpError> {
-        let t = self.http.player_play(self.guild, source, false).await?;
+        self.enqueue_with(source, PlayOptions::default()).await
+    }
 
-        self.queue.push(t.clone());
-        Ok(t)
+    /// Enqueues the provided track to be played using the provided options.
+    pub async fn enqueue_with(&mut self, source: impl PlaySource, options: PlayOptions)
+        -> Result<Track, HttpError>
+    {
+        self.play_with(source, false, options).await
     }
 
     /// Pauses the currently playing track and forces the provided one to play at arrival.
     pub async fn force_play(&mut self, source: impl PlaySource) -> Result<Track, HttpError> {
-        let t = self.http.player_play(self.guild, source, true).await?;
-        self.queue.insert(0, t.clone());
+        self.force_play_with(source, PlayOptions::default()).await
+    }
+
+    /// Pauses the currently playing track and forces the provided one to play at arrival using
+    /// the provided options.
+    pub async fn force_play_with(&mut self, source: impl PlaySource, options: PlayOptions)
+        -> Result<Track, HttpError>
+    {
+        self.play_with(source, true, options).await
+    }
+
+    async fn play_with(&mut self, source: impl PlaySource, force: bool, options: PlayOptions)
+        -> Result<Track, HttpError>
+    {
+        let t = self.http.player_play(self.guild, source, force, &options).await?;
+
+        if !options.no_replace {
+            options.apply(self, t.clone(), force);
+            return Ok(t);
+        }
+
+        match self.http.player_info(self.guild).await {
+            Ok(info) => self.update(info),
+            Err(e) => {
+                warn!("Failed to refresh the player of guild {} after playing: {e}", self.guild);
+                options.apply(self, t.clone(), force);
+            }
+        }
+
         Ok(t)
     }
 
@@ -105,3 +272,186 @@ impl Player {
         }
     }
 }
+
+/// Serializable state of a player, created by [`Player::snapshot`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerSnapshot {
+    /// The guild the player belongs to.
+    pub guild_id: NonZeroU64,
+    /// The queue of the player.
+    pub queue: Vec<Track>,
+    /// The track that was being played.
+    pub current: Option<Track>,
+    /// Whether the playback was paused.
+    pub paused: bool,
+    /// The volume of the player.
+    pub volume: u8,
+    /// The values stored using [`PlayerData::insert_persistent`], restored using
+    /// [`PlayerData::restore`].
+    pub data: HashMap<String, Value>
+}
+
+/// A cloneable handle to the player of a guild, which can be held across awaits.
+///
+/// Unlike [`ReferenceMut`], the handle doesn't hold any lock while waiting for the server, the
+/// local state of the player is only locked briefly to read it or update it once a request
+/// completes.
+///
+/// [`ReferenceMut`]: crate::reference::ReferenceMut
+#[derive(Clone)]
+pub struct PlayerHandle {
+    http: RestClient,
+    players: Arc<PlayerManager>,
+    guild: NonZeroU64
+}
+
+impl PlayerHandle {
+    pub(crate) fn new(http: RestClient, players: Arc<PlayerManager>, guild: NonZeroU64) -> Self {
+        Self {
+            http,
+            players,
+            guild
+        }
+    }
+
+    /// Returns a handle that applies the provided timeout to every request made through it,
+    /// see [`RestClient::with_timeout`].
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        Self {
+            http: self.http.with_timeout(timeout),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the id of the guild the player belongs to.
+    pub fn guild(&self) -> NonZeroU64 {
+        self.guild
+    }
+
+    /// Runs the provided closure with the local state of the player, returns `None` if the player
+    /// doesn't exist.
+    ///
+    /// The player is locked while the closure runs, so it must not block.
+    pub fn with<R>(&self, f: impl FnOnce(&Player) -> R) -> Option<R> {
+        self.players.players.get(&self.guild.get()).map(|p| f(&p))
+    }
+
+    /// Returns a copy of the local state of the player, if it exists.
+    pub fn get(&self) -> Option<Player> {
+        self.with(Clone::clone)
+    }
+
+    /// Returns the custom data attached to the player, if it exists.
+    pub fn data(&self) -> Option<Arc<PlayerData>> {
+        self.with(|p| Arc::clone(&p.data))
+    }
+
+    /// Returns a serializable snapshot of the state of the player, if it exists.
+    pub fn snapshot(&self) -> Option<PlayerSnapshot> {
+        self.with(Player::snapshot)
+    }
+
+    /// Returns the track that is currently being played, if any.
+    pub fn current(&self) -> Option<Track> {
+        self.with(|p| p.current.clone()).flatten()
+    }
+
+    /// Returns the queue of the player.
+    pub fn queue(&self) -> Vec<Track> {
+        self.with(|p| p.queue.clone()).unwrap_or_default()
+    }
+
+    /// Gets the information held by the server about the player.
+    pub async fn info(&self) -> Result<PlayerInfo, HttpError> {
+        self.http.player_info(self.guild).await
+    }
+
+    /// Enqueues the provided track to be played.
+    pub async fn enqueue(&self, source: impl PlaySource) -> Result<Track, HttpError> {
+        self.enqueue_with(source, PlayOptions::default()).await
+    }
+
+    /// Enqueues the provided track to be played using the provided options.
+    pub async fn enqueue_with(&self, source: impl PlaySource, options: PlayOptions)
+        -> Result<Track, HttpError>
+    {
+        self.play_with(source, false, options).await
+    }
+
+    /// Pauses the currently playing track and forces the provided one to play at arrival.
+    pub async fn force_play(&self, source: impl PlaySource) -> Result<Track, HttpError> {
+        self.force_play_with(source, PlayOptions::default()).await
+    }
+
+    /// Pauses the currently playing track and forces the provided one to play at arrival using
+    /// the provided options.
+    pub async fn force_play_with(&self, source: impl PlaySource, options: PlayOptions)
+        -> Result<Track, HttpError>
+    {
+        self.play_with(source, true, options).await
+    }
+
+    async fn play_with(&self, source: impl PlaySource, force: bool, options: PlayOptions)
+        -> Result<Track, HttpError>
+    {
+        let t = self.http.player_play(self.guild, source, force, &options).await?;
+
+        if !options.no_replace {
+            self.update(|p| options.apply(p, t.clone(), force));
+            return Ok(t);
+        }
+
+        match self.http.player_info(self.guild).await {
+            Ok(info) => self.update(|p| p.update(info)),
+            Err(e) => {
+                warn!("Failed to refresh the player of guild {} after playing: {e}", self.guild);
+                self.update(|p| options.apply(p, t.clone(), force));
+            }
+        }
+
+        Ok(t)
+    }
+
+    /// Pauses the playback if possible.
+    pub async fn pause(&self) -> Result<(), HttpError> {
+        if self.with(|p| p.paused) == Some(true) {
+            return Ok(());
+        }
+
+        self.http.player_pause(self.guild).await?;
+        self.update(|p| p.paused = true);
+
+        Ok(())
+    }
+
+    /// Resumes the playback if possible.
+    pub async fn resume(&self) -> Result<(), HttpError> {
+        if self.with(|p| p.paused) == Some(false) {
+            return Ok(());
+        }
+
+        self.http.player_resume(self.guild).await?;
+        self.update(|p| p.paused = false);
+
+        Ok(())
+    }
+
+    /// Sets a new volume, the default value is 100.
+    pub async fn set_volume(&self, volume: u8) -> Result<(), HttpError> {
+        if self.with(|p| p.volume) == Some(volume) {
+            return Ok(());
+        }
+
+        self.http.player_set_volume(self.guild, volume).await?;
+        self.update(|p| p.volume = volume);
+
+        Ok(())
+    }
+
+    /// Updates the local state of the player, if it still exists.
+    fn update(&self, f: impl FnOnce(&mut Player)) {
+        if let Some(mut player) = self.players.players.get_mut(&self.guild.get()) {
+            f(&mut player);
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{error, warn};
+use crate::error::HttpError;
+use crate::middleware::{Middleware, RouteInfo};
+use crate::rest::HttpResponse;
+use crate::tap::Direction;
+use crate::NightingaleClient;
+
+/// How fast a recording is replayed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    /// Payloads are replayed one after another, without waiting.
+    Instant,
+    /// Payloads are replayed with the same delays they were recorded with.
+    RealTime,
+    /// Payloads are replayed with the recorded delays divided by the provided factor.
+    Accelerated(f64)
+}
+
+impl ReplaySpeed {
+    fn delay(&self, recorded: Duration) -> Option<Duration> {
+        match self {
+            Self::Instant => None,
+            Self::RealTime => Some(recorded),
+            Self::Accelerated(factor) if *factor > 0.0 => Some(recorded.div_f64(*factor)),
+            Self::Accelerated(_) => None
+        }
+    }
+}
+
+/// A single line of a recording.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum Record {
+    Gateway {
+        /// Milliseconds since the unix epoch.
+        timestamp: u64,
+        direction: RecordDirection,
+        payload: Value
+    },
+    Rest {
+        /// Milliseconds since the unix epoch.
+        timestamp: u64,
+        route: String,
+        guild: Option<u64>,
+        status: Option<u16>,
+        body: Option<Value>,
+        error: Option<String>,
+        elapsed: u64
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RecordDirection {
+    Incoming,
+    Outgoing
+}
+
+impl From<Direction> for RecordDirection {
+    fn from(value: Direction) -> Self {
+        match value {
+            Direction::Incoming => Self::Incoming,
+            Direction::Outgoing => Self::Outgoing
+        }
+    }
+}
+
+/// Records gateway payloads and REST exchanges to a JSONL file, which can be replayed later
+/// using [`NightingaleClient::replay`].
+///
+/// REST exchanges are recorded by registering the recorder as a middleware in
+/// [`Config::middleware`], and gateway payloads by calling [`Recorder::record_gateway`].
+/// Passwords and voice tokens are redacted from the recording.
+///
+/// Records are written to the file by a dedicated thread, so recording never blocks the
+/// runtime. The thread stops once every clone of the recorder is dropped.
+///
+/// [`Config::middleware`]: crate::config::Config::middleware
+#[derive(Clone)]
+pub struct Recorder {
+    sender: Sender<Record>
+}
+
+impl Recorder {
+    /// Creates a recorder writing to the provided file, appending to it if it already exists.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+
+        let (sender, receiver) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("nightingale-recorder".to_string())
+            .spawn(move || write_records(receiver, BufWriter::new(file)))?;
+
+        Ok(Self {
+            sender
+        })
+    }
+
+    /// Starts recording the gateway payloads sent and received by the provided client, until
+    /// the client is shut down.
+    pub fn record_gateway(&self, client: &NightingaleClient) {
+        let mut tap = client.tap();
+        let this = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let frame = match tap.recv().await {
+                    Ok(frame) => frame,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Recorder lagged behind, {} payloads were not recorded", skipped);
+                        continue;
+                    },
+                    Err(RecvError::Closed) => return
+                };
+
+                this.write(Record::Gateway {
+                    timestamp: timestamp(frame.timestamp),
+                    direction: frame.direction.into(),
+                    payload: frame.payload
+                });
+            }
+        });
+    }
+
+    fn write(&self, record: Record) {
+        // The writer thread only stops after every sender is dropped.
+        let _ = self.sender.send(record);
+    }
+}
+
+impl Middleware for Recorder {
+    fn after_response(
+        &self,
+        route: &RouteInfo,
+        response: Result<&HttpResponse, &HttpError>,
+        elapsed: Duration
+    ) {
+        let (status, body, error) = match response {
+            Ok(response) => {
+                let body = serde_json::from_slice(response.body()).unwrap_or_else(|_| {
+                    Value::String(String::from_utf8_lossy(response.body()).into_owned())
+                });
+
+                (Some(response.status().as_u16()), Some(body), None)
+            },
+            Err(e) => (e.status().map(|s| s.as_u16()), None, Some(e.to_string()))
+        };
+
+        self.write(Record::Rest {
+            timestamp: timestamp(SystemTime::now()),
+            route: route.name.to_string(),
+            guild: route.guild.map(|g| g.get()),
+            status,
+            body,
+            error,
+            elapsed: elapsed.as_millis() as u64
+        });
+    }
+}
+
+/// Writes the received records to the file, flushing it whenever there are no more records
+/// waiting to be written.
+fn write_records(receiver: Receiver<Record>, mut writer: BufWriter<File>) {
+    let mut record = receiver.recv();
+
+    while let Ok(current) = record {
+        let result = serde_json::to_writer(&mut writer, &current)
+            .map_err(io::Error::from)
+            .and_then(|_| writer.write_all(b"\n"));
+
+        if let Err(e) = result {
+            error!("Failed to write recording: {e}");
+        }
+
+        record = match receiver.try_recv() {
+            Ok(next) => Ok(next),
+            Err(TryRecvError::Empty) => {
+                if let Err(e) = writer.flush() {
+                    error!("Failed to write recording: {e}");
+                }
+
+                receiver.recv()
+            },
+            Err(TryRecvError::Disconnected) => break
+        };
+    }
+
+    if let Err(e) = writer.flush() {
+        error!("Failed to write recording: {e}");
+    }
+}
+
+/// Reads the incoming gateway payloads of a recording, along with the delay since the previous
+/// one.
+pub(crate) fn read_incoming(path: &Path, speed: ReplaySpeed)
+    -> io::Result<Vec<(Option<Duration>, Value)>>
+{
+    let reader = BufReader::new(File::open(path)?);
+    let mut payloads = Vec::new();
+    let mut last = None;
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record = serde_json::from_str(&line)?;
+        let Record::Gateway { timestamp, direction: RecordDirection::Incoming, payload } = record else {
+            continue;
+        };
+
+        let recorded = last.map(|last| Duration::from_millis(timestamp.saturating_sub(last)))
+            .unwrap_or_default();
+        last = Some(timestamp);
+
+        payloads.push((speed.delay(recorded), payload));
+    }
+
+    Ok(payloads)
+}
+
+fn timestamp(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -1,9 +1,14 @@
 use std::fmt::format;
 use std::num::NonZeroU64;
 use std::sync::Arc;
+use std::time::Duration;
 use parking_lot::RwLock;
-use reqwest::{Error, Client, Response};
-use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderValue};
+use std::time::Instant;
+use bytes::Bytes;
+use reqwest::{Client, Method, Request, RequestBuilder, StatusCode};
+use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderValue, RETRY_AFTER};
+use tracing::{debug_span, Instrument};
+use futures::{stream, Stream, TryStreamExt};
 use serde::de::DeserializeOwned;
 use serde_json::json;
 use uuid::Uuid;
@@ -11,38 +16,119 @@ use crate::error::{HttpError, StatusCodeError};
 use crate::model::error::ErrorResponse;
 use crate::model::info::Info;
 use crate::model::player::PlayerInfo;
+use crate::model::search::PlaylistPage;
+use crate::model::session::SessionInfo;
 use crate::model::track::Track;
+use tracing::{debug, warn};
 use crate::Shared;
-use crate::source::{PlaySource, SearchSource};
+use crate::middleware::{Middleware, RouteInfo};
+use crate::source::{PagedSearchRoute, PlaySource, SearchSource};
+use crate::player::PlayOptions;
+
+/// A response received from the server, with its body already read.
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes
+}
+
+impl HttpResponse {
+    /// The status code of the response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The headers of the response.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The body of the response.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+}
 
 #[derive(Clone)]
 pub struct RestClient {
     shared: Arc<Shared>,
-    http: Client
+    http: Client,
+    /// The error returned when building the HTTP client from the config, reported by every
+    /// request instead of panicking.
+    http_error: Option<Arc<reqwest::Error>>,
+    auth: HeaderValue,
+    timeout: Option<Duration>
 }
 
 impl RestClient {
     pub(crate) fn new(shared: Arc<Shared>) -> Self {
-        let pass = shared.config.read().password.clone();
+        let config = shared.config.read();
 
-        let mut headers = HeaderMap::new();
-        headers.insert(AUTHORIZATION, HeaderValue::from_str(&pass).expect("Invalid password"));
+        let mut auth = HeaderValue::from_str(&config.password).expect("Invalid password");
+        auth.set_sensitive(true);
+
+        let (http, http_error) = match &config.http_client {
+            Some(client) => (client.clone(), None),
+            None => {
+                let mut builder = Client::builder();
+
+                if let Some(timeout) = config.connect_timeout {
+                    builder = builder.connect_timeout(timeout);
+                }
+
+                if let Some(tls) = &config.tls {
+                    builder = builder.use_preconfigured_tls(tls.clone());
+                }
+
+                if let Some(proxy) = &config.proxy {
+                    builder = builder.proxy(proxy.clone());
+                }
+
+                match builder.build() {
+                    Ok(client) => (client, None),
+                    Err(e) => (Client::new(), Some(Arc::new(e)))
+                }
+            }
+        };
+
+        drop(config);
 
         Self {
             shared,
-            http: Client::builder().default_headers(headers).build().unwrap()
+            http,
+            http_error,
+            auth,
+            timeout: None
         }
     }
 
-    fn base_api_route(&self) -> String {
+    /// Returns a client that applies the provided timeout to every request made through it,
+    /// overriding both the request and search timeouts set in the [`Config`].
+    ///
+    /// [`Config`]: crate::config::Config
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self.clone()
+        }
+    }
+
+    /// Applies the search timeout to the provided request, if any.
+    fn search_request(&self, request: RequestBuilder) -> RequestBuilder {
         let config = self.shared.config.read();
-        let schema = if config.ssl {
-            "https://"
-        } else {
-            "http://"
-        };
 
-        format!("{schema}{}:{}/api/v1", config.host, config.port)
+        match self.timeout.or(config.search_timeout).or(config.request_timeout) {
+            Some(timeout) => request.timeout(timeout),
+            None => request
+        }
+    }
+
+    fn base_api_route(&self) -> String {
+        // An invalid endpoint is reported by `send_inner` before the request is built.
+        self.shared.endpoint.as_ref()
+            .map(|endpoint| endpoint.http_url("/api/v1"))
+            .unwrap_or_default()
     }
 
     fn session(&self) -> Uuid {
@@ -56,8 +142,9 @@ impl RestClient {
     {
         let _ = source;
         deserialize_json::<Vec<S::Track>>(
-            self.http.get(format!("{}/search{}", self.base_api_route(), S::track(query)))
-                .send()
+            self.send(RouteInfo::new("search"), self.search_request(
+                self.http.get(format!("{}/search{}", self.base_api_route(), S::track(query)))
+            ))
                 .await?
         ).await
     }
@@ -69,12 +156,86 @@ impl RestClient {
     {
         let _ = source;
         deserialize_json::<S::Playlist>(
-            self.http.get(format!("{}/search{}", self.base_api_route(), S::playlist(playlist)))
-                .send()
+            self.send(RouteInfo::new("playlist"), self.search_request(
+                self.http.get(format!("{}/search{}", self.base_api_route(), S::playlist(playlist)))
+            ))
+                .await?
+        ).await
+    }
+
+    /// Queries a single page of the playlist, starting from the page identified by `continuation`,
+    /// or from the first one if `None`.
+    pub async fn playlist_page<S>(
+        &self,
+        playlist: String,
+        continuation: Option<String>,
+        source: S
+    ) -> Result<PlaylistPage<S::Track>, HttpError>
+    where
+        S: SearchSource + PagedSearchRoute
+    {
+        let _ = source;
+        self.fetch_playlist_page::<S>(playlist, continuation).await
+    }
+
+    async fn fetch_playlist_page<S>(
+        &self,
+        playlist: String,
+        continuation: Option<String>
+    ) -> Result<PlaylistPage<S::Track>, HttpError>
+    where
+        S: SearchSource + PagedSearchRoute
+    {
+        deserialize_json::<PlaylistPage<S::Track>>(
+            self.send(RouteInfo::new("playlist_page"), self.search_request(self.http.get(format!(
+                "{}/search{}",
+                self.base_api_route(),
+                S::playlist_page(playlist, continuation)
+            ))))
                 .await?
         ).await
     }
 
+    /// Returns a stream that lazily yields the playlist items, requesting the next page only when
+    /// the tracks of the previous one have been consumed.
+    ///
+    /// If an error occurs while requesting a page, it is yielded and the stream finishes.
+    pub fn playlist_stream<S>(
+        &self,
+        playlist: String,
+        source: S
+    ) -> impl Stream<Item = Result<S::Track, HttpError>>
+    where
+        S: SearchSource + PagedSearchRoute
+    {
+        enum State {
+            Start,
+            Next(String),
+            Done
+        }
+
+        let _ = source;
+        let this = self.clone();
+
+        stream::try_unfold(State::Start, move |state| {
+            let this = this.clone();
+            let playlist = playlist.clone();
+
+            async move {
+                let continuation = match state {
+                    State::Start => None,
+                    State::Next(c) => Some(c),
+                    State::Done => return Ok::<_, HttpError>(None)
+                };
+
+                let page = this.fetch_playlist_page::<S>(playlist, continuation).await?;
+                let next = page.continuation.map(State::Next).unwrap_or(State::Done);
+
+                Ok(Some((stream::iter(page.tracks.into_iter().map(Ok)), next)))
+            }
+        }).try_flatten()
+    }
+
     /// Returns information about the server. If `current_session` is set to `true`, then the playback
     /// field will only represent the current session players.
     pub async fn server_info(&self, current_session: bool) -> Result<Info, HttpError> {
@@ -85,15 +246,38 @@ impl RestClient {
             url.push_str(self.session().to_string().as_str());
         }
 
-        let res = self.http.get(url)
-            .send()
-            .await?;
+        deserialize_json(self.send(RouteInfo::new("server_info"), self.http.get(url)).await?).await
+    }
 
-        if res.status().is_success() {
-            res.json().await.map_err(From::from)
-        } else {
-            Err(HttpError::UnexpectedStatus(StatusCodeError(res.status())))
-        }
+    /// Returns information about all the players of the current session.
+    pub async fn players(&self) -> Result<Vec<PlayerInfo>, HttpError> {
+        let session = self.session();
+        let url = format!("{}/{session}/players", self.base_api_route());
+
+        deserialize_json(self.send(RouteInfo::new("players"), self.http.get(url)).await?).await
+    }
+
+    /// Returns information about all the sessions present on the server.
+    pub async fn sessions(&self) -> Result<Vec<SessionInfo>, HttpError> {
+        let url = format!("{}/sessions", self.base_api_route());
+
+        deserialize_json(self.send(RouteInfo::new("sessions"), self.http.get(url)).await?).await
+    }
+
+    /// Returns information about the provided session.
+    pub async fn session_info(&self, session: Uuid) -> Result<SessionInfo, HttpError> {
+        let url = format!("{}/sessions/{session}", self.base_api_route());
+
+        deserialize_json(self.send(RouteInfo::new("session_info"), self.http.get(url)).await?).await
+    }
+
+    /// Destroys the provided session, disconnecting and removing all of its players.
+    ///
+    /// Destroying the current session will also close the connection to the server.
+    pub async fn destroy_session(&self, session: Uuid) -> Result<(), HttpError> {
+        let url = format!("{}/sessions/{session}", self.base_api_route());
+
+        self.send(RouteInfo::new("destroy_session"), self.http.delete(url)).await.map(|_| ())
     }
 
     pub(crate) async fn connect(&self, guild: NonZeroU64, channel: NonZeroU64) -> Result<(), HttpError> {
@@ -103,36 +287,14 @@ impl RestClient {
             "{}/{session}/players/{guild}/connect?channel_id={channel}",
             self.base_api_route()
         );
-        let res = self.http.put(url)
-            .send()
-            .await?;
-
-        if res.status().is_success() {
-            Ok(())
-        } else {
-            Err(res.json::<ErrorResponse>()
-                .await
-                .map(HttpError::ErrorMessage)
-                .unwrap_or_else(From::from))
-        }
+        self.send(RouteInfo::guild("connect", guild), self.http.put(url)).await.map(|_| ())
     }
 
     pub(crate) async fn disconnect(&self, guild: NonZeroU64) -> Result<(), HttpError>
     {
         let session = self.session();
         let url = format!("{}/{session}/players/{guild}/disconnect", self.base_api_route());
-        let res = self.http.delete(url)
-            .send()
-            .await?;
-
-        if res.status().is_success() {
-            Ok(())
-        } else {
-            Err(res.json::<ErrorResponse>()
-                .await
-                .map(HttpError::ErrorMessage)
-                .unwrap_or_else(From::from))
-        }
+        self.send(RouteInfo::guild("disconnect", guild), self.http.delete(url)).await.map(|_| ())
     }
 
     pub(crate) async fn player_info(&self, guild: NonZeroU64) -> Result<PlayerInfo, HttpError>
@@ -140,59 +302,63 @@ impl RestClient {
         let session = self.session();
         let url = format!("{}/{session}/players/{guild}/info", self.base_api_route());
 
-        deserialize_json(self.http.get(url).send().await?).await
+        deserialize_json(
+            self.send(RouteInfo::guild("player_info", guild), self.http.get(url)).await?
+        ).await
     }
 
     pub(crate) async fn player_play<S>(
         &self,
         guild: NonZeroU64,
         source: S,
-        force: bool
+        force: bool,
+        options: &PlayOptions
     ) -> Result<Track, HttpError>
     where
         S: PlaySource
     {
-        let body = json!({
+        options.validate(force)?;
+
+        let mut body = json!({
             "force_play": force,
-            "source": source.value_for()
+            "source": source.value_for(),
+            "paused": options.paused,
+            "no_replace": options.no_replace
         });
+
+        if let Some(start) = options.start {
+            body["start_time"] = json!(start.as_millis() as u64);
+        }
+
+        if let Some(end) = options.end {
+            body["end_time"] = json!(end.as_millis() as u64);
+        }
+
+        if let Some(volume) = options.volume {
+            body["volume"] = json!(volume);
+        }
+
         let session = self.session();
 
         let url = format!("{}/{session}/players/{guild}/play", self.base_api_route());
 
-        deserialize_json(self.http.post(url).json(&body).send().await?).await
+        deserialize_json(
+            self.send(RouteInfo::guild("player_play", guild), self.http.post(url).json(&body)).await?
+        ).await
     }
 
     pub(crate) async fn player_pause(&self, guild: NonZeroU64) -> Result<(), HttpError>
     {
         let session = self.session();
         let url = format!("{}/{session}/players/{guild}/pause", self.base_api_route());
-        let res = self.http.patch(url).send().await?;
-
-        if res.status().is_success() {
-            Ok(())
-        } else {
-            Err(res.json::<ErrorResponse>()
-                .await
-                .map(HttpError::ErrorMessage)
-                .unwrap_or_else(From::from))
-        }
+        self.send(RouteInfo::guild("player_pause", guild), self.http.patch(url)).await.map(|_| ())
     }
 
     pub(crate) async fn player_resume(&self, guild: NonZeroU64) -> Result<(), HttpError>
     {
         let session = self.session();
         let url = format!("{}/{session}/players/{guild}/resume", self.base_api_route());
-        let res = self.http.patch(url).send().await?;
-
-        if res.status().is_success() {
-            Ok(())
-        } else {
-            Err(res.json::<ErrorResponse>()
-                .await
-                .map(HttpError::ErrorMessage)
-                .unwrap_or_else(From::from))
-        }
+        self.send(RouteInfo::guild("player_resume", guild), self.http.patch(url)).await.map(|_| ())
     }
 
     pub(crate) async fn player_set_volume(
@@ -203,26 +369,255 @@ impl RestClient {
     {
         let session = self.session();
         let url = format!("{}/{session}/players/{guild}/set_volume/{volume}", self.base_api_route());
-        let res = self.http.patch(url).send().await?;
-
-        if res.status().is_success() {
-            Ok(())
-        } else {
-            Err(res.json::<ErrorResponse>()
-                .await
-                .map(HttpError::ErrorMessage)
-                .unwrap_or_else(From::from))
+        self.send(RouteInfo::guild("player_set_volume", guild), self.http.patch(url))
+            .await
+            .map(|_| ())
+    }
+
+    /// Sends the request, retrying it following the configured [`RetryPolicy`] and executing the
+    /// registered middlewares around every attempt. Responses with a non-success status code are
+    /// converted into errors.
+    ///
+    /// [`RetryPolicy`]: crate::config::RetryPolicy
+    async fn send(
+        &self,
+        route: RouteInfo,
+        request: RequestBuilder
+    ) -> Result<HttpResponse, HttpError> {
+        let span = debug_span!(
+            "nightingale_request",
+            route = route.name,
+            guild = route.guild.map(NonZeroU64::get)
+        );
+
+        self.send_inner(route, request).instrument(span).await
+    }
+
+    async fn send_inner(
+        &self,
+        route: RouteInfo,
+        request: RequestBuilder
+    ) -> Result<HttpResponse, HttpError> {
+        self.shared.endpoint()?;
+        if let Some(e) = &self.http_error {
+            return Err(HttpError::Client(Arc::clone(e)));
+        }
+
+        let mut request = request.build()?;
+        let (policy, middleware) = {
+            let config = self.shared.config.read();
+
+            if request.timeout().is_none() {
+                *request.timeout_mut() = self.timeout.or(config.request_timeout);
+            }
+
+            (config.retry.clone(), config.middleware.clone())
+        };
+        let retryable = policy.non_idempotent
+            || matches!(*request.method(), Method::GET | Method::PUT | Method::PATCH);
+        let mut retries = 0;
+
+        loop {
+            let Some(attempt) = request.try_clone() else {
+                return into_result(self.attempt(&route, &middleware, request).await, retries);
+            };
+
+            let result = self.attempt(&route, &middleware, attempt).await;
+
+            let delay = match &result {
+                Ok(res) if matches!(
+                    res.status,
+                    StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+                ) => Some(retry_after(res).unwrap_or_else(|| policy.backoff(retries))),
+                Err(HttpError::Reqwest(e)) if e.is_connect() || e.is_request() || e.is_body() =>
+                    Some(policy.backoff(retries)),
+                Err(HttpError::Transport(_)) => Some(policy.backoff(retries)),
+                _ => None
+            };
+
+            match delay {
+                Some(delay) if retryable && retries < policy.max_retries => {
+                    retries += 1;
+                    let delay = delay.min(policy.max_delay);
+
+                    warn!(
+                        "Request {} {} failed, retrying in {:?} [Retry {}/{}]",
+                        request.method(),
+                        request.url().path(),
+                        delay,
+                        retries,
+                        policy.max_retries
+                    );
+
+                    tokio::time::sleep(delay).await;
+                },
+                _ => {
+                    if retries > 0 {
+                        debug!(
+                            "Request {} {} finished after {} retries",
+                            request.method(),
+                            request.url().path(),
+                            retries
+                        );
+                    }
+
+                    return into_result(result, retries)
+                }
+            }
+        }
+    }
+
+    /// Executes a single attempt of a request, running the middlewares around it.
+    async fn attempt(
+        &self,
+        route: &RouteInfo,
+        middleware: &[Arc<dyn Middleware>],
+        mut request: Request
+    ) -> Result<HttpResponse, HttpError> {
+        request.headers_mut().insert(AUTHORIZATION, self.auth.clone());
+
+        for m in middleware {
+            m.before_request(route, &mut request);
         }
+
+        let started = Instant::now();
+        let result = self.execute(request).await;
+
+        for m in middleware {
+            m.after_response(route, result.as_ref(), started.elapsed());
+        }
+
+        result
     }
 
+    async fn execute(&self, request: Request) -> Result<HttpResponse, HttpError> {
+        #[cfg(unix)]
+        if let Some(path) = self.shared.endpoint.as_ref().ok().and_then(|endpoint| endpoint.unix_path()) {
+            let timeout = request.timeout().copied();
+            let fut = unix::execute(path, request);
+
+            return match timeout {
+                Some(timeout) => tokio::time::timeout(timeout, fut).await
+                    .map_err(|_| HttpError::Timeout(None))?,
+                None => fut.await
+            };
+        }
+
+        let response = self.http.execute(request).await?;
+
+        Ok(HttpResponse {
+            status: response.status(),
+            headers: response.headers().clone(),
+            body: response.bytes().await?
+        })
+    }
 }
 
-async fn deserialize_json<M: DeserializeOwned>(response: Response) -> Result<M, HttpError> {
-    if response.status().is_success() {
-        Ok(response.json().await?)
+async fn deserialize_json<M: DeserializeOwned>(response: HttpResponse) -> Result<M, HttpError> {
+    Ok(serde_json::from_slice(&response.body)?)
+}
+
+/// Converts the result of a request into an error if the request failed or the server responded
+/// with a non-success status code, attaching the number of retries made if any.
+fn into_result(
+    result: Result<HttpResponse, HttpError>,
+    retries: u32
+) -> Result<HttpResponse, HttpError> {
+    let error = match result {
+        Ok(response) if response.status.is_success() => return Ok(response),
+        Ok(response) => {
+            let status = response.status;
+
+            serde_json::from_slice::<ErrorResponse>(&response.body)
+                .map(|error| HttpError::ErrorMessage(ErrorResponse {
+                    status: Some(status),
+                    ..error
+                }))
+                .unwrap_or_else(|_| HttpError::UnexpectedStatus(StatusCodeError(status)))
+        },
+        Err(e) => e
+    };
+
+    if retries > 0 {
+        Err(HttpError::Retried {
+            retries,
+            error: Box::new(error)
+        })
     } else {
-        Err(response.json::<ErrorResponse>().await
-            .map(HttpError::ErrorMessage)
-            .unwrap_or_else(From::from))
+        Err(error)
+    }
+}
+
+/// Returns the delay requested by the server in the `Retry-After` header, if present.
+///
+/// Only the delay in seconds form is supported, headers using the HTTP date form or values that
+/// don't fit in a [`Duration`] are ignored, falling back to the backoff of the retry policy.
+fn retry_after(response: &HttpResponse) -> Option<Duration> {
+    let secs = response.headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<f64>()
+        .ok()?;
+
+    Duration::try_from_secs_f64(secs).ok()
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::path::Path;
+    use hyper::Body;
+    use hyper::client::conn;
+    use hyper::header::HOST;
+    use reqwest::Request;
+    use reqwest::header::HeaderValue;
+    use tokio::net::UnixStream;
+    use tracing::debug;
+    use crate::error::HttpError;
+    use super::HttpResponse;
+
+    /// Executes the request over a new connection to the provided unix domain socket.
+    pub(super) async fn execute(path: &Path, request: Request) -> Result<HttpResponse, HttpError> {
+        let stream = UnixStream::connect(path).await
+            .map_err(|e| HttpError::Transport(Box::new(e)))?;
+        let (mut sender, connection) = conn::handshake(stream).await
+            .map_err(|e| HttpError::Transport(Box::new(e)))?;
+
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                debug!("Unix socket connection closed with error: {e}");
+            }
+        });
+
+        let uri = match request.url().query() {
+            Some(query) => format!("{}?{query}", request.url().path()),
+            None => request.url().path().to_string()
+        };
+
+        let body = request.body()
+            .and_then(|b| b.as_bytes())
+            .map(|b| Body::from(b.to_vec()))
+            .unwrap_or_else(Body::empty);
+
+        let mut req = hyper::Request::builder()
+            .method(request.method().clone())
+            .uri(uri)
+            .body(body)
+            .map_err(|e| HttpError::Transport(Box::new(e)))?;
+
+        *req.headers_mut() = request.headers().clone();
+        req.headers_mut().insert(HOST, HeaderValue::from_static("localhost"));
+
+        let response = sender.send_request(req).await
+            .map_err(|e| HttpError::Transport(Box::new(e)))?;
+        let (parts, body) = response.into_parts();
+
+        Ok(HttpResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body: hyper::body::to_bytes(body).await
+                .map_err(|e| HttpError::Transport(Box::new(e)))?
+        })
     }
 }
@@ -65,11 +65,11 @@ impl VoiceGatewayManager for NightingaleVoiceManager {
     }
 
     async fn register_shard(&self, shard_id: u32, sender: UnboundedSender<ShardRunnerMessage>) {
-        self.sender.send(ToSocketMessage::RegisterShard(shard_id, sender)).unwrap();
+        let _ = self.sender.send(ToSocketMessage::RegisterShard(shard_id, sender));
     }
 
     async fn deregister_shard(&self, shard_id: u32) {
-        self.sender.send(ToSocketMessage::DeregisterShard(shard_id)).unwrap()
+        let _ = self.sender.send(ToSocketMessage::DeregisterShard(shard_id));
     }
 
     async fn server_update(&self, guild_id: GuildId, endpoint: &Option<String>, token: &str) {
@@ -82,7 +82,7 @@ impl VoiceGatewayManager for NightingaleVoiceManager {
             }
         });
 
-        self.sender.send(ToSocketMessage::Send(value)).unwrap();
+        let _ = self.sender.send(ToSocketMessage::Send(value));
     }
 
     async fn state_update(&self, guild_id: GuildId, voice_state: &VoiceState) {
@@ -96,6 +96,6 @@ impl VoiceGatewayManager for NightingaleVoiceManager {
             }
         });
 
-        self.sender.send(ToSocketMessage::Send(value)).unwrap();
+        let _ = self.sender.send(ToSocketMessage::Send(value));
     }
 }
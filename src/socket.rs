@@ -2,27 +2,38 @@ use std::{pin::Pin, task::{Context, Poll}};
 use std::cell::UnsafeCell;
 use std::collections::HashMap;
 use std::future::Future;
+use std::io;
 use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
 use parking_lot::{Mutex, RwLock};
-use tokio::net::TcpStream;
-use tokio_tungstenite::{connect_async, tungstenite::{handshake::client::Request, Error, Message}, MaybeTlsStream, WebSocketStream};
+use tokio_tungstenite::{client_async_tls_with_config, Connector, tungstenite::{Error, Message}, MaybeTlsStream, WebSocketStream};
 use futures::{ready, SinkExt, Stream, StreamExt};
 use tokio::sync::mpsc::{unbounded_channel, UnboundedSender, UnboundedReceiver};
-use tracing::{error, info, warn};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{debug, error, info, warn};
+use serde_json::Value;
 use uuid::Uuid;
 use futures::channel::mpsc::UnboundedSender as Sender;
 use tokio_tungstenite::tungstenite::protocol::CloseFrame;
 use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
-use crate::{error::SocketError, model::gateway::IncomingPayload, PlayerManager, Shared};
-
-use crate::config::Config;
-use crate::model::gateway::state::UpdateState;
+use crate::{error::SocketError, model::gateway::{IncomingFrame, IncomingPayload}, PlayerManager, Shared};
+
+use crate::codec::Codec;
+use crate::outbox::Outbox;
+use crate::tap::Direction;
+use crate::endpoint::BoxedIo;
+use crate::model::gateway::close::DisconnectReason;
+use crate::model::gateway::connection::ConnectionState;
 use crate::msg::{FromSocketMessage, ToSocketMessage};
 
 #[cfg(feature = "serenity")]
 use crate::events::EventHandler;
 #[cfg(feature = "serenity")]
-use crate::model::gateway::event::Event;
+use crate::config::DispatchMode;
+#[cfg(feature = "serenity")]
+use crate::dispatch::{self, ConnectionEvent, GuildDispatcher, GuildEvent};
 #[cfg(feature = "serenity")]
 use serenity::gateway::ShardRunnerMessage;
 use tokio_tungstenite::tungstenite::client::IntoClientRequest;
@@ -32,28 +43,63 @@ use tokio_tungstenite::tungstenite::http::HeaderValue;
 use twilight_gateway::MessageSender;
 #[cfg(feature = "twilight")]
 use crate::events::IncomingEvent;
+#[cfg(feature = "twilight")]
+use crate::stream::{GUILD_EVENTS_CAPACITY, SharedReceiver};
+#[cfg(feature = "twilight")]
+use tokio::sync::broadcast;
+
+/// State of the heartbeats sent to the server.
+#[derive(Default)]
+struct Heartbeat {
+    /// Nonce of the last heartbeat sent.
+    nonce: u64,
+    /// Nonce and send time of the heartbeat waiting for an answer, if any.
+    pending: Option<(u64, Instant)>,
+    /// Number of consecutive heartbeats that went unanswered.
+    missed: u32
+}
 
 pub struct SocketHandle {
     pub sender: UnboundedSender<ToSocketMessage>,
     pub receiver: UnboundedReceiver<FromSocketMessage>,
+    /// The socket task, which returns the last sequence number received once stopped.
+    pub task: Option<JoinHandle<Option<u64>>>,
     #[cfg(feature = "twilight")]
-    pub events: Mutex<Option<UnboundedReceiver<IncomingEvent>>>,
+    pub events: SharedReceiver,
+    #[cfg(feature = "twilight")]
+    pub guild_events: broadcast::Sender<IncomingEvent>
 }
 
 /// A websocket client to te gateway.
 pub(crate) struct Socket {
-    stream: Option<WebSocketStream<MaybeTlsStream<TcpStream>>>,
+    stream: Option<WebSocketStream<MaybeTlsStream<BoxedIo>>>,
     shared: Arc<Shared>,
     players: Arc<PlayerManager>,
     sender: UnboundedSender<FromSocketMessage>,
+    heartbeat: Heartbeat,
+    codec: Codec,
+    outbox: Outbox,
+    /// Sequence number of the last payload received in the current session.
+    sequence: Option<u64>,
     #[cfg(feature = "serenity")]
     events: Arc<dyn EventHandler + 'static>,
     #[cfg(feature = "serenity")]
     shards: HashMap<u32, Sender<ShardRunnerMessage>>,
+    #[cfg(feature = "serenity")]
+    guilds: Option<GuildDispatcher>,
+    /// Delivers the connection events to the handler in order.
+    #[cfg(feature = "serenity")]
+    connection: UnboundedSender<ConnectionEvent>,
     #[cfg(feature = "twilight")]
     shards: HashMap<u64, MessageSender>,
     #[cfg(feature = "twilight")]
-    events: UnboundedSender<IncomingEvent>
+    events: UnboundedSender<IncomingEvent>,
+    /// Fan-out of the guild events for the streams created by
+    /// [`NightingaleClient::guild_events`].
+    ///
+    /// [`NightingaleClient::guild_events`]: crate::NightingaleClient::guild_events
+    #[cfg(feature = "twilight")]
+    guild_events: broadcast::Sender<IncomingEvent>
 }
 
 impl Socket {
@@ -65,22 +111,30 @@ impl Socket {
     ) -> SocketHandle {
         let (tx, rx) = unbounded_channel();
         let (from_tx, from_rx) = unbounded_channel();
+        let codec = Codec::new(&shared.config.read());
         let this = Self {
             stream: None,
             shared,
             players,
             sender: from_tx,
+            heartbeat: Heartbeat::default(),
+            codec,
+            outbox: Outbox::default(),
+            sequence: None,
+            connection: dispatch::connection_worker(Arc::clone(&event_handler)),
             events: event_handler,
-            shards: HashMap::new()
+            shards: HashMap::new(),
+            guilds: None
         };
 
-        tokio::spawn(async move {
-            this.run(rx).await;
+        let task = tokio::spawn(async move {
+            this.run(rx).await
         });
 
         SocketHandle {
             sender: tx,
-            receiver: from_rx
+            receiver: from_rx,
+            task: Some(task)
         }
     }
 
@@ -93,54 +147,146 @@ impl Socket {
         let (to_tx, to_rx) = unbounded_channel();
         let (from_tx, from_rx) = unbounded_channel();
         let (events_tx, events_rx) = unbounded_channel();
+        let guild_events = broadcast::channel(GUILD_EVENTS_CAPACITY).0;
+        let codec = Codec::new(&shared.config.read());
 
         let this = Self {
             stream: None,
             shared,
             players,
             sender: from_tx,
+            heartbeat: Heartbeat::default(),
+            codec,
+            outbox: Outbox::default(),
+            sequence: None,
             shards,
-            events: events_tx
+            events: events_tx,
+            guild_events: guild_events.clone()
         };
 
-        tokio::spawn(async move {
-            this.run(to_rx).await;
+        let task = tokio::spawn(async move {
+            this.run(to_rx).await
         });
 
         SocketHandle {
             sender: to_tx,
             receiver: from_rx,
-            events: Mutex::new(Some(events_rx))
+            task: Some(task),
+            events: Arc::new(Mutex::new(Some(events_rx))),
+            guild_events
         }
     }
 
-    async fn run(mut self, mut receiver: UnboundedReceiver<ToSocketMessage>) {
+    async fn run(mut self, mut receiver: UnboundedReceiver<ToSocketMessage>) -> Option<u64> {
+        let heartbeat_interval = self.shared.config.read().heartbeat_interval;
+        let mut heartbeat = interval(heartbeat_interval.unwrap_or(Duration::MAX));
+        heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
         loop {
             tokio::select! {
                 biased;
                 msg = receiver.recv() => {
-                    let Some(msg) = msg else { continue; };
+                    let Some(msg) = msg else { return self.sequence; };
 
-                    if let ToSocketMessage::Kill = &msg {
-                        return;
+                    if let ToSocketMessage::Kill { persist } = msg {
+                        self.close(persist).await;
+                        return self.sequence;
                     }
 
                     self.handle_msg(msg).await
                 },
-                Some(payload) = self.next() => self.handle_payload(payload)
+                _ = heartbeat.tick(), if heartbeat_interval.is_some() => self.heartbeat().await,
+                Some(payload) = self.next() => self.handle_payload(payload).await
             }
         }
     }
 
-    fn connect_uri(&self) -> String {
-        let config = self.shared.config.read();
-        let proto = if config.ssl {
-            "wss://"
+    /// Sends a heartbeat to the server, considering the connection dead if too many of them
+    /// went unanswered.
+    async fn heartbeat(&mut self) {
+        let Some(stream) = self.stream.as_mut() else { return; };
+
+        if self.heartbeat.pending.is_some() {
+            self.heartbeat.missed += 1;
+            let max = self.shared.config.read().max_missed_heartbeats;
+
+            if self.heartbeat.missed >= max {
+                warn!("Server didn't answer {} heartbeats, reconnecting", self.heartbeat.missed);
+                self.connection_lost(None).await;
+                return;
+            }
+        }
+
+        self.heartbeat.nonce = self.heartbeat.nonce.wrapping_add(1);
+        let nonce = self.heartbeat.nonce;
+
+        if let Err(e) = stream.send(Message::Ping(nonce.to_be_bytes().to_vec())).await {
+            warn!("Failed to send heartbeat: {e}");
+        }
+
+        self.heartbeat.pending = Some((nonce, Instant::now()));
+    }
+
+    /// Handles a pong frame received from the server, updating the latency if it answers the
+    /// last heartbeat sent.
+    fn handle_pong(&mut self, data: &[u8]) {
+        let Some((nonce, sent)) = self.heartbeat.pending else { return; };
+
+        if data != nonce.to_be_bytes() {
+            return;
+        }
+
+        *self.shared.latency.write() = Some(sent.elapsed());
+        self.heartbeat.pending = None;
+        self.heartbeat.missed = 0;
+    }
+
+    /// Drops the current connection and tries to resume the session if possible, or to open a new
+    /// one otherwise.
+    async fn connection_lost(&mut self, reason: Option<DisconnectReason>) {
+        self.connection_closed(reason);
+
+        let resume = self.shared.session_config.read().enable_resume
+            && *self.shared.session.read() != Uuid::nil();
+
+        if resume {
+            self.handle_msg(ToSocketMessage::Resume).await;
         } else {
-            "ws://"
+            self.handle_msg(ToSocketMessage::Reconnect).await;
+        }
+    }
+
+    /// Drops the current connection without trying to reconnect.
+    fn connection_closed(&mut self, reason: Option<DisconnectReason>) {
+        self.stream = None;
+        self.sender_send(FromSocketMessage::Disconnected);
+        self.dispatch_disconnect(reason);
+        self.set_state(ConnectionState::Disconnected);
+    }
+
+    /// Resumes the current session, requesting the events after the last sequence received.
+    async fn resume(&mut self) {
+        self.try_disconnect().await;
+        let session = *self.shared.session.read();
+
+        assert_ne!(session, Uuid::nil());
+
+        let url = match self.sequence {
+            Some(seq) => format!("{}/resume/{}?seq={}", self.connect_uri(), session, seq),
+            None => format!("{}/resume/{}", self.connect_uri(), session)
         };
 
-        format!("{}{}:{}/ws", proto, config.host, config.port)
+        info!("Trying to resume session");
+
+        self.set_state(ConnectionState::Resuming);
+        self.try_connect(url).await;
+    }
+
+    fn connect_uri(&self) -> String {
+        // An invalid endpoint is reported by `connect` before the url is used.
+        self.shared.endpoint.as_ref()
+            .map(|endpoint| endpoint.ws_url("/ws"))
+            .unwrap_or_default()
     }
 
     fn sender_send(&mut self, msg: FromSocketMessage) {
@@ -163,29 +309,25 @@ impl Socket {
                     )
                 };
 
+                self.sequence = None;
+                self.set_state(ConnectionState::Connecting);
                 self.try_connect(url).await;
             }
             ToSocketMessage::Disconnect => self.try_disconnect().await,
-            ToSocketMessage::Resume => {
-                self.try_disconnect().await;
-                let session = *self.shared.session.read();
-
-                assert_ne!(session, Uuid::nil());
-
-                let url = format!("{}/resume/{}", self.connect_uri(), session);
-
-                info!("Trying to resume session");
-
-                self.try_connect(url).await;
+            ToSocketMessage::Replay(frame) => self.handle_payload_inner(*frame, true),
+            ToSocketMessage::Resume => self.resume().await,
+            ToSocketMessage::ResumeSession(session) => {
+                *self.shared.session.write() = session.id;
+                self.sequence = session.seq;
+                self.resume().await;
             },
             ToSocketMessage::Send(payload) => {
-                let Ok(serialized) = serde_json::to_string(&payload) else {
-                    error!("Failed to serialize payload");
-                    return;
-                };
+                if !self.outbox.is_empty() {
+                    self.flush().await;
+                }
 
-                if let Some(socket) = self.stream.as_mut() {
-                    let _ = socket.send(Message::Text(serialized)).await;
+                if let Err(payload) = self.send(payload).await {
+                    self.buffer(payload);
                 }
             },
             #[cfg(feature = "serenity")]
@@ -209,6 +351,77 @@ impl Socket {
         })).await;
 
         self.sender_send(FromSocketMessage::Disconnected);
+        self.set_state(ConnectionState::Disconnected);
+    }
+
+    /// Sends a payload to the server, returning it back if there is no connection.
+    async fn send(&mut self, payload: Value) -> Result<(), Value> {
+        let Some(socket) = self.stream.as_mut() else { return Err(payload); };
+
+        let msg = match self.codec.encode(&payload) {
+            Ok(msg) => msg,
+            Err(e) => {
+                error!("Failed to serialize payload: {e}");
+                return Ok(());
+            }
+        };
+
+        match socket.send(msg).await {
+            Ok(_) => {
+                if self.shared.tap.is_enabled() {
+                    self.shared.tap.publish(Direction::Outgoing, payload);
+                }
+
+                Ok(())
+            },
+            Err(e) => {
+                warn!("Failed to send payload: {e}");
+                Err(payload)
+            }
+        }
+    }
+
+    /// Buffers a payload until the connection is established again.
+    fn buffer(&mut self, payload: Value) {
+        let limit = self.shared.config.read().outgoing_buffer_size;
+        let dropped = self.outbox.push(payload, limit);
+
+        if dropped > 0 {
+            warn!("Outgoing buffer is full, dropped {} messages", dropped);
+            self.shared.dropped_messages.fetch_add(dropped as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Sends the buffered payloads in the order they were queued.
+    async fn flush(&mut self) {
+        while let Some(payload) = self.outbox.pop() {
+            if let Err(payload) = self.send(payload).await {
+                self.outbox.requeue(payload);
+                return;
+            }
+        }
+    }
+
+    /// Closes the connection before stopping the socket task.
+    async fn close(&mut self, persist: bool) {
+        let Some(mut conn) = self.stream.take() else { return; };
+
+        let frame = if persist {
+            CloseFrame {
+                code: CloseCode::Away,
+                reason: "Client restarting".into()
+            }
+        } else {
+            CloseFrame {
+                code: CloseCode::Normal,
+                reason: "Client shutting down".into()
+            }
+        };
+
+        let _ = conn.close(Some(frame)).await;
+
+        info!("Closed connection to nightingale server");
+        self.set_state(ConnectionState::Disconnected);
     }
 
     async fn try_connect(&mut self, url: String) -> bool {
@@ -218,6 +431,8 @@ impl Socket {
                 Ok(_) => {
                     info!("Connected to nightingale server successfully!");
                     self.sender_send(FromSocketMessage::ConnectedSuccessfully);
+                    self.set_state(ConnectionState::Connected);
+                    self.flush().await;
                     return true;
                 },
                 Err(error) => {
@@ -235,55 +450,117 @@ impl Socket {
         }
 
         error!("Failed to connect to nightingale server after {} attempts", attempts);
+        self.set_state(ConnectionState::Failed);
         false
     }
 
-    fn handle_payload(&mut self, incoming: Result<IncomingPayload, SocketError>) {
+    async fn handle_payload(&mut self, incoming: Result<IncomingFrame, SocketError>) {
         match incoming {
-            Ok(payload) => {
-                self.handle_payload_inner(payload);
+            Ok(frame) => {
+                if self.track_sequence(&frame) {
+                    self.handle_payload_inner(frame, false);
+                }
             },
             Err(error) => match error {
-                SocketError::Deserialize(e) => {
+                SocketError::Decode(e) => {
                     error!("Failed to deserialize payload: {e:?}");
                 },
                 SocketError::Tungstenite(e) => {
                     error!("Disconnected from server, error: {e}");
-                    self.stream = None;
-                    self.sender_send(FromSocketMessage::Disconnected);
+                    self.connection_lost(None).await;
+                },
+                SocketError::Closed(reason) => {
+                    match &reason {
+                        Some(r) => warn!("Server closed the connection, code: {}, reason: {}", r.code, r.reason),
+                        None => warn!("Server closed the connection")
+                    }
+
+                    if reason.as_ref().is_none_or(DisconnectReason::should_reconnect) {
+                        self.connection_lost(reason).await;
+                    } else {
+                        self.connection_closed(reason);
+                    }
                 }
             }
         }
     }
 
+    /// Updates the last sequence number received, returns `false` if the frame was already
+    /// received before.
+    fn track_sequence(&mut self, frame: &IncomingFrame) -> bool {
+        let Some(seq) = frame.seq else { return true; };
+
+        if let Some(last) = self.sequence {
+            if seq <= last {
+                debug!("Ignoring already received payload with sequence {}", seq);
+                return false;
+            }
+
+            if seq > last + 1 {
+                warn!("Missed events with sequence {} to {}", last + 1, seq - 1);
+                self.dispatch_missed(last + 1, seq - 1);
+            }
+        }
+
+        self.sequence = Some(seq);
+        true
+    }
+
+    #[cfg(feature = "serenity")]
+    fn dispatch_missed(&self, first: u64, last: u64) {
+        let _ = self.connection.send(ConnectionEvent::EventsMissed { first, last });
+    }
+
+    #[cfg(feature = "twilight")]
+    fn dispatch_missed(&self, first: u64, last: u64) {
+        let _ = self.events.send(IncomingEvent::EventsMissed { first, last });
+    }
+
+    /// Updates the connection state, notifying the watchers and the event handler if it changed.
+    fn set_state(&self, state: ConnectionState) {
+        if !self.shared.state.send_if_modified(|current| std::mem::replace(current, state) != state) {
+            return;
+        }
+
+        self.dispatch_state(state);
+    }
+
+    #[cfg(feature = "serenity")]
+    fn dispatch_state(&self, state: ConnectionState) {
+        let _ = self.connection.send(ConnectionEvent::State(state));
+    }
+
+    #[cfg(feature = "twilight")]
+    fn dispatch_state(&self, state: ConnectionState) {
+        let _ = self.events.send(IncomingEvent::ConnectionState(state));
+    }
+
     #[cfg(feature = "serenity")]
-    fn handle_payload_inner(&mut self, payload: IncomingPayload) {
-        let events = Arc::clone(&self.events);
-        match payload {
+    fn dispatch_disconnect(&self, reason: Option<DisconnectReason>) {
+        let _ = self.connection.send(ConnectionEvent::Disconnected(reason));
+    }
+
+    #[cfg(feature = "twilight")]
+    fn dispatch_disconnect(&self, reason: Option<DisconnectReason>) {
+        let _ = self.events.send(IncomingEvent::Disconnected(reason));
+    }
+
+    #[cfg(feature = "serenity")]
+    /// Handles a decoded frame, `recorded` frames come from a replayed recording and must not
+    /// change the session or be forwarded to the shards.
+    fn handle_payload_inner(&mut self, frame: IncomingFrame, recorded: bool) {
+        match frame.payload {
             IncomingPayload::Ready(r) => {
-                *self.shared.session.write() = r.session;
+                if !recorded {
+                    *self.shared.session.write() = r.session;
+                }
 
-                tokio::spawn(async move {
-                    events.on_ready(r).await;
-                });
+                let _ = self.connection.send(ConnectionEvent::Ready(r));
             },
-            IncomingPayload::UpdateState(state) => match state {
-                UpdateState::ConnectGateway(data) => {
-                    tokio::spawn(async move {
-                        events.on_gateway_connect(data).await;
-                    });
-                },
-                UpdateState::ReconnectGateway(data) => {
-                    tokio::spawn(async move {
-                        events.on_gateway_reconnect(data).await;
-                    });
-                },
-                UpdateState::DisconnectGateway(data) => {
-                    tokio::spawn(async move {
-                        events.on_gateway_disconnect(data).await;
-                    });
-                }
+            IncomingPayload::UpdateState(state) => {
+                self.dispatch_guild(state.guild_id().get(), GuildEvent::UpdateState(state));
             },
+            IncomingPayload::Forward(_) if recorded => (),
             IncomingPayload::Forward(forward) => {
                 let Some(shard) = self.shards.get(&(forward.shard as u32)) else {
                     error!("Shard {} not found", forward.shard);
@@ -298,29 +575,52 @@ impl Socket {
                 shard.unbounded_send(ShardRunnerMessage::Message(Message::Text(payload))).unwrap()
             }
             IncomingPayload::Event { guild_id, event } => {
+                self.dispatch_guild(guild_id, GuildEvent::Event {
+                    event,
+                    replayed: frame.replayed
+                });
+            }
+        }
+    }
+
+    #[cfg(feature = "serenity")]
+    /// Delivers an event that belongs to a guild using the configured dispatch mode.
+    fn dispatch_guild(&mut self, guild: u64, event: GuildEvent) {
+        let (mode, timeout) = {
+            let config = self.shared.config.read();
+            (config.dispatch_mode, config.handler_timeout)
+        };
+
+        match mode {
+            DispatchMode::Concurrent => {
+                let events = Arc::clone(&self.events);
                 let players = Arc::clone(&self.players);
 
                 tokio::spawn(async move {
-                    let player = players.get_or_insert(guild_id);
-
-                    match event {
-                        Event::TrackStart(t) => events.on_track_start(&*player, t).await,
-                        Event::TrackEnd(t) => events.on_track_end(&*player, t).await,
-                        Event::TrackErrored(t) => events.on_track_errored(&*player, t).await
-                    }
+                    dispatch::deliver(&*events, &players, guild, event, timeout).await;
                 });
+            },
+            DispatchMode::OrderedPerGuild => {
+                let guilds = self.guilds.get_or_insert_with(|| GuildDispatcher::new(
+                    Arc::clone(&self.events),
+                    Arc::clone(&self.players),
+                    Arc::clone(&self.shared)
+                ));
+
+                guilds.dispatch(guild, event);
             }
         }
     }
 
     #[cfg(feature = "twilight")]
-    fn handle_payload_inner(&mut self, payload: IncomingPayload) {
-        let p = match payload {
-            IncomingPayload::Ready(r) => {
+    /// Handles a decoded frame, `recorded` frames come from a replayed recording and must not
+    /// change the session or be forwarded to the shards.
+    fn handle_payload_inner(&mut self, frame: IncomingFrame, recorded: bool) {
+        match &frame.payload {
+            IncomingPayload::Ready(r) if !recorded => {
                 *self.shared.session.write() = r.session;
-
-                IncomingEvent::Ready(r)
             },
+            IncomingPayload::Forward(_) if recorded => return,
             IncomingPayload::Forward(p) => {
                 let Some(sender) = self.shards.get(&p.shard) else {
                     error!("Shard {} not found", p.shard);
@@ -336,48 +636,104 @@ impl Socket {
 
                 return;
             },
-            other => other.into()
-        };
+            _ => ()
+        }
+
+        let event = IncomingEvent::from(frame);
 
-        let _ = self.events.send(p).unwrap();
+        if event.guild_id().is_some() && self.guild_events.receiver_count() > 0 {
+            let _ = self.guild_events.send(event.clone());
+        }
+
+        let _ = self.events.send(event);
     }
 
     async fn connect(&mut self, url: &str) -> Result<(), Error>{
-        let mut req = url.into_client_request().unwrap();
+        let endpoint = self.shared.endpoint()
+            .map_err(|e| Error::Io(io::Error::new(io::ErrorKind::InvalidInput, e)))?;
+        let (password, timeout, tls, codec) = {
+            let config = self.shared.config.read();
+            (config.password.clone(), config.connect_timeout, config.tls.clone(), Codec::new(&config))
+        };
+
+        let separator = if url.contains('?') { '&' } else { '?' };
+        let mut req = format!("{url}{separator}{}", codec.query()).into_client_request().unwrap();
 
         req.headers_mut().insert(
             AUTHORIZATION,
-            HeaderValue::from_str(self.shared.config.read().password.clone().as_str()).unwrap()
+            HeaderValue::from_str(password.as_str()).unwrap()
         );
 
-        let (connection, _) = connect_async(req).await?;
+        let connect = async move {
+            let stream = endpoint.connect().await?;
+            client_async_tls_with_config(req, stream, None, tls.map(Connector::NativeTls)).await
+        };
+
+        let (connection, _) = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, connect).await
+                .map_err(|_| Error::Io(io::ErrorKind::TimedOut.into()))??,
+            None => connect.await?
+        };
 
         self.stream = Some(connection);
+        self.heartbeat = Heartbeat::default();
+        self.codec = codec;
 
         Ok(())
     }
 }
 
 impl Stream for Socket {
-    type Item = Result<IncomingPayload, SocketError>;
+    type Item = Result<IncomingFrame, SocketError>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let this = self.get_mut();
-        let Some(socket) = this.stream.as_mut() else { return Poll::Ready(None) };
 
-        let msg = match ready!(Pin::new(socket).poll_next(cx)) {
-            None => return Poll::Ready(None),
-            Some(Err(e)) => {
-                return Poll::Ready(Some(Err(From::from(e))));
-            },
-            Some(Ok(msg)) => msg
-        };
+        loop {
+            let Some(socket) = this.stream.as_mut() else { return Poll::Ready(None) };
 
-        let data = match msg {
-            Message::Text(t) => t,
-            _ => return Poll::Pending
-        };
+            let msg = match ready!(Pin::new(socket).poll_next(cx)) {
+                None => return Poll::Ready(Some(Err(SocketError::Closed(None)))),
+                Some(Err(e)) => {
+                    return Poll::Ready(Some(Err(From::from(e))));
+                },
+                Some(Ok(msg)) => msg
+            };
+
+            let payload = match msg {
+                Message::Text(t) => {
+                    if this.shared.tap.is_enabled() {
+                        let raw = this.codec.decode_text(&t).unwrap_or(Value::String(t.clone()));
+                        this.shared.tap.publish(Direction::Incoming, raw);
+                    }
+
+                    this.codec.decode_text(&t).map_err(From::from)
+                },
+                Message::Binary(data) => {
+                    let data = match this.codec.inflate(&data) {
+                        Ok(Some(data)) => data,
+                        Ok(None) => continue,
+                        Err(e) => return Poll::Ready(Some(Err(From::from(e))))
+                    };
+
+                    if this.shared.tap.is_enabled() {
+                        if let Ok(raw) = this.codec.decode_binary(&data) {
+                            this.shared.tap.publish(Direction::Incoming, raw);
+                        }
+                    }
 
-        Poll::Ready(Some(serde_json::from_str(&data).map_err(From::from)))
+                    this.codec.decode_binary(&data).map_err(From::from)
+                },
+                Message::Pong(data) => {
+                    this.handle_pong(&data);
+                    continue;
+                },
+                // tungstenite queues the reply to pings by itself and flushes it on the next read.
+                Message::Ping(_) | Message::Frame(_) => continue,
+                Message::Close(frame) => Err(SocketError::Closed(frame.map(From::from)))
+            };
+
+            return Poll::Ready(Some(payload));
+        }
     }
 }
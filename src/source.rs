@@ -11,6 +11,13 @@ pub trait SearchRoute {
     fn playlist(playlist: String) -> String;
 }
 
+/// Represents sources whose playlists can be queried one page at a time.
+pub trait PagedSearchRoute: SearchRoute {
+    /// Returns the route to query a single page of `playlist`, starting from `continuation` if
+    /// provided, or from the first page otherwise.
+    fn playlist_page(playlist: String, continuation: Option<String>) -> String;
+}
+
 /// Represents a source that can be used to search and query tracks.
 pub trait SearchSource: SearchRoute {
     /// The track type returned from search calls.
@@ -22,6 +29,7 @@ pub trait SearchSource: SearchRoute {
 /// Youtube source.
 ///
 /// This is the only search source supported at the moment.
+#[derive(Clone, Copy)]
 pub struct Youtube;
 
 impl SearchRoute for Youtube {
@@ -34,6 +42,22 @@ impl SearchRoute for Youtube {
     }
 }
 
+impl PagedSearchRoute for Youtube {
+    fn playlist_page(playlist: String, continuation: Option<String>) -> String {
+        let mut route = format!(
+            "/youtube/playlist/paged?playlist_id={}",
+            urlencoding::encode(&playlist)
+        );
+
+        if let Some(continuation) = continuation {
+            route.push_str("&continuation=");
+            route.push_str(&urlencoding::encode(&continuation));
+        }
+
+        route
+    }
+}
+
 impl SearchSource for Youtube {
     type Track = YoutubeTrack;
     type Playlist = YoutubePlaylist;
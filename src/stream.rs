@@ -1,29 +1,42 @@
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
-use futures::Stream;
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt};
 use parking_lot::Mutex;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::mpsc::UnboundedReceiver;
+use tracing::warn;
 use crate::events::IncomingEvent;
+use crate::model::gateway::event::{Event, TrackEnd};
+
+pub(crate) type SharedReceiver = Arc<Mutex<Option<UnboundedReceiver<IncomingEvent>>>>;
+
+/// Number of guild events kept for slow [`GuildEventStream`]s before they start lagging behind.
+pub(crate) const GUILD_EVENTS_CAPACITY: usize = 256;
 
 /// Stream that can be used to receive events from the server. Only one instance can be active at
 /// a time.
-pub struct EventStream<'a> {
-    mutex: &'a Mutex<Option<UnboundedReceiver<IncomingEvent>>>,
+///
+/// The stream owns its receiver, so it can be moved into other tasks freely.
+pub struct EventStream {
+    mutex: SharedReceiver,
     recv: Option<UnboundedReceiver<IncomingEvent>>
 }
 
-impl<'a> EventStream<'a> {
-    pub(crate) fn new(mutex: &'a Mutex<Option<UnboundedReceiver<IncomingEvent>>>) -> Option<Self> {
+impl EventStream {
+    pub(crate) fn new(mutex: &SharedReceiver) -> Option<Self> {
         let recv = mutex.lock().take()?;
 
         Some(Self {
-            mutex,
+            mutex: Arc::clone(mutex),
             recv: Some(recv)
         })
     }
 }
 
-impl<'a> Stream for EventStream<'a> {
+impl Stream for EventStream {
     type Item = IncomingEvent;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
@@ -31,8 +44,61 @@ impl<'a> Stream for EventStream<'a> {
     }
 }
 
-impl<'a> Drop for EventStream<'a> {
+impl Drop for EventStream {
     fn drop(&mut self) {
         *self.mutex.lock() = self.recv.take();
     }
 }
+
+/// Stream that only yields the events of a single guild, created by
+/// [`NightingaleClient::guild_events`].
+///
+/// Any number of guild streams can be active at a time, they receive a copy of the events and
+/// don't take them from the [`EventStream`]. A stream that falls behind by more than 256 events
+/// skips the oldest ones.
+///
+/// [`NightingaleClient::guild_events`]: crate::NightingaleClient::guild_events
+pub struct GuildEventStream {
+    inner: BoxStream<'static, IncomingEvent>
+}
+
+impl GuildEventStream {
+    pub(crate) fn new(receiver: Receiver<IncomingEvent>, guild: u64) -> Self {
+        let inner = futures::stream::unfold(receiver, move |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) if event.guild_id() == Some(guild) => return Some((event, receiver)),
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Event stream of guild {} lagged behind, {} events were skipped", guild, skipped);
+                    },
+                    Err(RecvError::Closed) => return None
+                }
+            }
+        });
+
+        Self {
+            inner: inner.boxed()
+        }
+    }
+
+    /// Waits for the next track to end in the guild, discarding the events received in the
+    /// meantime. Returns `None` if the stream ended.
+    pub async fn next_track_end(&mut self) -> Option<TrackEnd> {
+        while let Some(event) = self.next().await {
+            if let IncomingEvent::Event { event: Event::TrackEnd(end), .. } = event {
+                return Some(end);
+            }
+        }
+
+        None
+    }
+}
+
+impl Stream for GuildEventStream {
+    type Item = IncomingEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().inner.poll_next_unpin(cx)
+    }
+}
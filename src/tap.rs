@@ -0,0 +1,84 @@
+use std::time::SystemTime;
+use serde_json::Value;
+use tokio::sync::broadcast::{self, Receiver, Sender};
+use tracing::trace;
+use crate::config::Config;
+
+/// Number of frames kept for slow subscribers before they start lagging behind.
+const TAP_CAPACITY: usize = 256;
+/// Keys whose values are replaced before a payload leaves the tap.
+const REDACTED_KEYS: [&str; 3] = ["password", "token", "authorization"];
+
+/// The direction of a gateway payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// The payload was received from the server.
+    Incoming,
+    /// The payload was sent to the server.
+    Outgoing
+}
+
+/// A raw gateway payload, with passwords and voice tokens redacted.
+#[derive(Debug, Clone)]
+pub struct RawFrame {
+    /// The time the payload was received or sent.
+    pub timestamp: SystemTime,
+    /// Whether the payload was received or sent.
+    pub direction: Direction,
+    /// The payload itself, payloads that aren't valid JSON are stored as a string.
+    pub payload: Value
+}
+
+/// Publishes raw gateway payloads to subscribers and to `tracing`.
+pub(crate) struct Tap {
+    sender: Sender<RawFrame>,
+    trace: bool
+}
+
+impl Tap {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            sender: broadcast::channel(TAP_CAPACITY).0,
+            trace: config.trace_payloads
+        }
+    }
+
+    /// Returns whether someone is listening, payloads are only decoded for the tap if so.
+    pub fn is_enabled(&self) -> bool {
+        self.trace || self.sender.receiver_count() > 0
+    }
+
+    pub fn subscribe(&self) -> Receiver<RawFrame> {
+        self.sender.subscribe()
+    }
+
+    pub fn publish(&self, direction: Direction, mut payload: Value) {
+        redact(&mut payload);
+
+        if self.trace {
+            trace!(target: "nightingale_client::tap", "{:?}: {}", direction, payload);
+        }
+
+        let _ = self.sender.send(RawFrame {
+            timestamp: SystemTime::now(),
+            direction,
+            payload
+        });
+    }
+}
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if REDACTED_KEYS.iter().any(|k| key.eq_ignore_ascii_case(k)) {
+                    *value = Value::String(String::from("[redacted]"));
+                } else {
+                    redact(value);
+                }
+            }
+        },
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => ()
+    }
+}